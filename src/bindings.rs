@@ -0,0 +1,521 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use winit::{
+    event::{ElementState, MouseButton, WindowEvent},
+    keyboard::{Key, ModifiersState, NamedKey},
+};
+
+// Everything a key or mouse button can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    Quit,
+    ToggleHelp,
+    ToggleMenu,
+    ToggleFullscreen,
+    ToggleTransparent,
+    CycleSampleRate,
+    CycleShape,
+    CycleLayout,
+    ToggleWaterfall,
+    CycleSparklines,
+    ToggleLabels,
+    ToggleHeatmap,
+    CycleGround,
+    NextView,
+    SaveView,
+    StartTour,
+    TogglePause,
+    StepBack,
+    StepForward,
+    GoLive,
+    ToggleRecording,
+    ReplaySlower,
+    ReplayFaster,
+    ReplayBack,
+    ReplayForward,
+    Pin,
+    DragWindow,
+    CameraUp,
+    CameraDown,
+    CameraForward,
+    CameraBackward,
+    CameraLeft,
+    CameraRight,
+    CameraOrbit,
+    Fit,
+    PresetTopDown,
+    PresetIsometric,
+    PresetFront,
+    PresetOrbit,
+}
+
+impl Action {
+    pub const ALL: [Action; 39] = [
+        Action::Quit,
+        Action::ToggleHelp,
+        Action::ToggleMenu,
+        Action::ToggleFullscreen,
+        Action::ToggleTransparent,
+        Action::CycleSampleRate,
+        Action::CycleShape,
+        Action::CycleLayout,
+        Action::ToggleWaterfall,
+        Action::CycleSparklines,
+        Action::ToggleLabels,
+        Action::ToggleHeatmap,
+        Action::CycleGround,
+        Action::NextView,
+        Action::SaveView,
+        Action::StartTour,
+        Action::TogglePause,
+        Action::StepBack,
+        Action::StepForward,
+        Action::GoLive,
+        Action::ToggleRecording,
+        Action::ReplaySlower,
+        Action::ReplayFaster,
+        Action::ReplayBack,
+        Action::ReplayForward,
+        Action::Pin,
+        Action::DragWindow,
+        Action::CameraUp,
+        Action::CameraDown,
+        Action::CameraForward,
+        Action::CameraBackward,
+        Action::CameraLeft,
+        Action::CameraRight,
+        Action::CameraOrbit,
+        Action::Fit,
+        Action::PresetTopDown,
+        Action::PresetIsometric,
+        Action::PresetFront,
+        Action::PresetOrbit,
+    ];
+
+    // Name in the [bindings] section of the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::ToggleHelp => "toggle_help",
+            Action::ToggleMenu => "toggle_menu",
+            Action::ToggleFullscreen => "toggle_fullscreen",
+            Action::ToggleTransparent => "toggle_transparent",
+            Action::CycleSampleRate => "cycle_sample_rate",
+            Action::CycleShape => "cycle_shape",
+            Action::CycleLayout => "cycle_layout",
+            Action::ToggleWaterfall => "toggle_waterfall",
+            Action::CycleSparklines => "cycle_sparklines",
+            Action::ToggleLabels => "toggle_labels",
+            Action::ToggleHeatmap => "toggle_heatmap",
+            Action::CycleGround => "cycle_ground",
+            Action::NextView => "next_view",
+            Action::SaveView => "save_view",
+            Action::StartTour => "start_tour",
+            Action::TogglePause => "toggle_pause",
+            Action::StepBack => "step_back",
+            Action::StepForward => "step_forward",
+            Action::GoLive => "go_live",
+            Action::ToggleRecording => "toggle_recording",
+            Action::ReplaySlower => "replay_slower",
+            Action::ReplayFaster => "replay_faster",
+            Action::ReplayBack => "replay_back",
+            Action::ReplayForward => "replay_forward",
+            Action::Pin => "pin",
+            Action::DragWindow => "drag_window",
+            Action::CameraUp => "camera_up",
+            Action::CameraDown => "camera_down",
+            Action::CameraForward => "camera_forward",
+            Action::CameraBackward => "camera_backward",
+            Action::CameraLeft => "camera_left",
+            Action::CameraRight => "camera_right",
+            Action::CameraOrbit => "camera_orbit",
+            Action::Fit => "fit",
+            Action::PresetTopDown => "preset_top_down",
+            Action::PresetIsometric => "preset_isometric",
+            Action::PresetFront => "preset_front",
+            Action::PresetOrbit => "preset_orbit",
+        }
+    }
+
+    // What the action does, for the help overlay
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit, or close this help or the settings",
+            Action::ToggleHelp => "Show or hide this help",
+            Action::ToggleMenu => "Show or hide the settings",
+            Action::ToggleFullscreen => "Toggle fullscreen",
+            Action::ToggleTransparent => "Toggle the transparent background",
+            Action::CycleSampleRate => "Cycle the sample rate",
+            Action::CycleShape => "Cycle the bar shape",
+            Action::CycleLayout => "Cycle the layout",
+            Action::ToggleWaterfall => "Toggle the waterfall history",
+            Action::CycleSparklines => "Cycle the sparklines",
+            Action::ToggleLabels => "Toggle the labels",
+            Action::ToggleHeatmap => "Toggle the heatmap",
+            Action::CycleGround => "Cycle the ground",
+            Action::NextView => "Switch to the next saved view",
+            Action::SaveView => "Save the current view",
+            Action::StartTour => "Start the camera tour",
+            Action::TogglePause => "Freeze the display, sampling goes on",
+            Action::StepBack => "Step back through the history while paused",
+            Action::StepForward => "Step forward through the history while paused",
+            Action::GoLive => "Back to the live view",
+            Action::ToggleRecording => "Start or stop recording the samples to a file",
+            Action::ReplaySlower => "Halve the replay speed",
+            Action::ReplayFaster => "Double the replay speed",
+            Action::ReplayBack => "Jump 10 seconds back in the replay",
+            Action::ReplayForward => "Jump 10 seconds ahead in the replay",
+            Action::Pin => "Pin the bar under the cursor, or drag the window",
+            Action::DragWindow => "Drag the window",
+            Action::CameraUp => "Raise the camera target",
+            Action::CameraDown => "Lower the camera target",
+            Action::CameraForward => "Move the camera closer",
+            Action::CameraBackward => "Move the camera away",
+            Action::CameraLeft => "Turn the camera left",
+            Action::CameraRight => "Turn the camera right",
+            Action::CameraOrbit => "Orbit the camera while dragging",
+            Action::Fit => "Fit the camera to the scene",
+            Action::PresetTopDown => "Top-down view",
+            Action::PresetIsometric => "Isometric view",
+            Action::PresetFront => "Front view",
+            Action::PresetOrbit => "Orbit view",
+        }
+    }
+
+    // Pin falls back to dragging the window when there is no bar under the
+    // cursor, so drag_window is only needed on a button of its own
+    fn defaults(self) -> &'static [&'static str] {
+        match self {
+            Action::Quit => &["Escape"],
+            Action::ToggleHelp => &["?", "F1"],
+            Action::ToggleMenu => &["Tab", "F2"],
+            Action::ToggleFullscreen => &["F"],
+            Action::ToggleTransparent => &["T"],
+            Action::CycleSampleRate => &["R"],
+            Action::CycleShape => &["M"],
+            Action::CycleLayout => &["L"],
+            Action::ToggleWaterfall => &["W"],
+            Action::CycleSparklines => &["S"],
+            Action::ToggleLabels => &["N"],
+            Action::ToggleHeatmap => &["O"],
+            Action::CycleGround => &["G"],
+            Action::NextView => &["V"],
+            Action::SaveView => &["Shift+V"],
+            Action::StartTour => &["A"],
+            Action::TogglePause => &["Space"],
+            Action::StepBack => &["Shift+ArrowLeft", ","],
+            Action::StepForward => &["Shift+ArrowRight", "."],
+            Action::GoLive => &["End"],
+            Action::ToggleRecording => &["Ctrl+R"],
+            Action::ReplaySlower => &["["],
+            Action::ReplayFaster => &["]"],
+            Action::ReplayBack => &["Ctrl+ArrowLeft"],
+            Action::ReplayForward => &["Ctrl+ArrowRight"],
+            Action::Pin => &["MouseLeft"],
+            Action::DragWindow => &[],
+            Action::CameraUp => &["PageUp"],
+            Action::CameraDown => &["PageDown"],
+            Action::CameraForward => &["ArrowUp"],
+            Action::CameraBackward => &["ArrowDown"],
+            Action::CameraLeft => &["ArrowLeft"],
+            Action::CameraRight => &["ArrowRight"],
+            Action::CameraOrbit => &["MouseRight"],
+            Action::Fit => &["0"],
+            Action::PresetTopDown => &["1"],
+            Action::PresetIsometric => &["2"],
+            Action::PresetFront => &["3"],
+            Action::PresetOrbit => &["4"],
+        }
+    }
+
+    // Held actions last from press to release, the others fire on press
+    pub fn is_held(self) -> bool {
+        matches!(
+            self,
+            Action::CameraUp
+                | Action::CameraDown
+                | Action::CameraForward
+                | Action::CameraBackward
+                | Action::CameraLeft
+                | Action::CameraRight
+                | Action::CameraOrbit
+        )
+    }
+
+    // Fired again while the key is held down
+    pub fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::StepBack | Action::StepForward | Action::ReplayBack | Action::ReplayForward
+        )
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.name() == name)
+            .ok_or_else(|| anyhow::anyhow!("unknown action '{}'", name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    // Characters are kept lowercase, Shift is a modifier like the others
+    Key(Key),
+    Mouse(MouseButton),
+}
+
+const NAMED_KEYS: [(&str, NamedKey); 35] = [
+    ("Space", NamedKey::Space),
+    ("Escape", NamedKey::Escape),
+    ("Enter", NamedKey::Enter),
+    ("Tab", NamedKey::Tab),
+    ("Backspace", NamedKey::Backspace),
+    ("Delete", NamedKey::Delete),
+    ("Insert", NamedKey::Insert),
+    ("Home", NamedKey::Home),
+    ("End", NamedKey::End),
+    ("PageUp", NamedKey::PageUp),
+    ("PageDown", NamedKey::PageDown),
+    ("ArrowUp", NamedKey::ArrowUp),
+    ("ArrowDown", NamedKey::ArrowDown),
+    ("ArrowLeft", NamedKey::ArrowLeft),
+    ("ArrowRight", NamedKey::ArrowRight),
+    ("Shift", NamedKey::Shift),
+    ("Ctrl", NamedKey::Control),
+    ("Alt", NamedKey::Alt),
+    ("Super", NamedKey::Super),
+    ("F1", NamedKey::F1),
+    ("F2", NamedKey::F2),
+    ("F3", NamedKey::F3),
+    ("F4", NamedKey::F4),
+    ("F5", NamedKey::F5),
+    ("F6", NamedKey::F6),
+    ("F7", NamedKey::F7),
+    ("F8", NamedKey::F8),
+    ("F9", NamedKey::F9),
+    ("F10", NamedKey::F10),
+    ("F11", NamedKey::F11),
+    ("F12", NamedKey::F12),
+    ("PrintScreen", NamedKey::PrintScreen),
+    ("Pause", NamedKey::Pause),
+    ("ContextMenu", NamedKey::ContextMenu),
+    ("CapsLock", NamedKey::CapsLock),
+];
+
+const MOUSE_BUTTONS: [(&str, MouseButton); 5] = [
+    ("MouseLeft", MouseButton::Left),
+    ("MouseRight", MouseButton::Right),
+    ("MouseMiddle", MouseButton::Middle),
+    ("MouseBack", MouseButton::Back),
+    ("MouseForward", MouseButton::Forward),
+];
+
+const MODIFIERS: [(&str, ModifiersState); 4] = [
+    ("Ctrl", ModifiersState::CONTROL),
+    ("Alt", ModifiersState::ALT),
+    ("Shift", ModifiersState::SHIFT),
+    ("Super", ModifiersState::SUPER),
+];
+
+// A key or mouse button with the modifiers held along with it, written like
+// "Ctrl+Shift+R" or "Alt+MouseLeft"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub modifiers: ModifiersState,
+    pub trigger: Trigger,
+}
+
+impl Binding {
+    // Modifier keys on their own set their own modifier while pressed, so
+    // those match whatever else is held. Symbols like '?' need Shift on most
+    // layouts, the character already says so.
+    fn matches(&self, trigger: &Trigger, mut modifiers: ModifiersState) -> bool {
+        let is_modifier = matches!(
+            self.trigger,
+            Trigger::Key(Key::Named(
+                NamedKey::Shift | NamedKey::Control | NamedKey::Alt | NamedKey::Super
+            ))
+        );
+        if let Trigger::Key(Key::Character(c)) = &self.trigger {
+            if c.to_lowercase() == c.to_uppercase() {
+                modifiers.remove(ModifiersState::SHIFT);
+            }
+        }
+        self.trigger == *trigger && (is_modifier || self.modifiers == modifiers)
+    }
+}
+
+impl FromStr for Binding {
+    type Err = anyhow::Error;
+
+    fn from_str(binding: &str) -> anyhow::Result<Self> {
+        // The last part is the key, so "Shift" alone is the Shift key
+        let mut parts = binding.split('+').map(str::trim).collect::<Vec<_>>();
+        let key = parts.pop().unwrap_or_default();
+        let mut modifiers = ModifiersState::empty();
+        for part in parts {
+            let (_, modifier) = MODIFIERS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(part))
+                .with_context(|| format!("unknown modifier '{}' in '{}'", part, binding))?;
+            modifiers |= *modifier;
+        }
+
+        let trigger = if let Some((_, key)) = NAMED_KEYS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        {
+            Trigger::Key(Key::Named(*key))
+        } else if let Some((_, button)) = MOUSE_BUTTONS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        {
+            Trigger::Mouse(*button)
+        } else if key.chars().count() == 1 {
+            Trigger::Key(Key::Character(key.to_lowercase().into()))
+        } else {
+            bail!("unknown key '{}' in '{}'", key, binding);
+        };
+        Ok(Binding { modifiers, trigger })
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, modifier) in MODIFIERS {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match &self.trigger {
+            Trigger::Key(Key::Character(c)) => write!(f, "{}", c.to_uppercase()),
+            Trigger::Key(Key::Named(key)) => {
+                let (name, _) = NAMED_KEYS.iter().find(|(_, k)| k == key).unwrap();
+                write!(f, "{}", name)
+            }
+            Trigger::Key(key) => write!(f, "{:?}", key),
+            Trigger::Mouse(button) => match MOUSE_BUTTONS.iter().find(|(_, b)| b == button) {
+                Some((name, _)) => write!(f, "{}", name),
+                None => write!(f, "{:?}", button),
+            },
+        }
+    }
+}
+
+// Which action each key and button is bound to
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    bindings: Vec<(Binding, Action)>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings::new(&BTreeMap::new()).expect("the default bindings conflict")
+    }
+}
+
+impl Bindings {
+    // The defaults, with the actions listed in `overrides` bound to the given
+    // keys instead. An empty list unbinds the action.
+    pub fn new(overrides: &BTreeMap<String, Vec<String>>) -> anyhow::Result<Bindings> {
+        let mut keys = Action::ALL
+            .into_iter()
+            .map(|action| {
+                let defaults = action
+                    .defaults()
+                    .iter()
+                    .map(|key| key.to_string())
+                    .collect();
+                (action, defaults)
+            })
+            .collect::<BTreeMap<Action, Vec<String>>>();
+        for (name, bound) in overrides {
+            let action = name.parse::<Action>().map_err(|e| {
+                let names = Action::ALL.map(Action::name);
+                anyhow::anyhow!(
+                    "bindings.{}: {}, expected one of {}",
+                    name,
+                    e,
+                    names.join(", ")
+                )
+            })?;
+            keys.insert(action, bound.clone());
+        }
+
+        let mut bindings: Vec<(Binding, Action)> = vec![];
+        for (action, keys) in keys {
+            for key in keys {
+                let binding = key
+                    .parse::<Binding>()
+                    .with_context(|| format!("bindings.{}", action))?;
+                match bindings.iter().find(|(other, _)| *other == binding) {
+                    Some((_, other)) if *other == action => {}
+                    Some((_, other)) => bail!(
+                        "{} is bound to both {} and {}, unbind one of them",
+                        binding,
+                        other,
+                        action
+                    ),
+                    None => bindings.push((binding, action)),
+                }
+            }
+        }
+        Ok(Bindings { bindings })
+    }
+
+    pub fn keys(&self, action: Action) -> impl Iterator<Item = &Binding> {
+        self.bindings
+            .iter()
+            .filter(move |(_, other)| *other == action)
+            .map(|(binding, _)| binding)
+    }
+
+    // Actions started or ended by the event, with true for pressed. Key
+    // repeats are ignored unless the action repeats, and held actions end on
+    // release whatever the modifiers are by then.
+    pub fn actions(&self, event: &WindowEvent, modifiers: ModifiersState) -> Vec<(Action, bool)> {
+        let (trigger, state, repeat) = match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                let key = match &event.logical_key {
+                    Key::Character(c) => Key::Character(c.to_lowercase().into()),
+                    key => key.clone(),
+                };
+                (Trigger::Key(key), event.state, event.repeat)
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                (Trigger::Mouse(*button), *state, false)
+            }
+            _ => return vec![],
+        };
+        match state {
+            ElementState::Pressed => self
+                .bindings
+                .iter()
+                .filter(|(binding, action)| {
+                    (!repeat || action.repeats()) && binding.matches(&trigger, modifiers)
+                })
+                .map(|(_, action)| (*action, true))
+                .collect(),
+            ElementState::Released => self
+                .bindings
+                .iter()
+                .filter(|(binding, action)| action.is_held() && binding.trigger == trigger)
+                .map(|(_, action)| (*action, false))
+                .collect(),
+        }
+    }
+}
@@ -1,14 +1,86 @@
 use std::time::{Duration, Instant};
 
-use cgmath::{Angle, Rotation3, SquareMatrix};
+use crate::bindings::Action;
+use crate::config::CameraConfig;
+use crate::gesture::{Gesture, GestureTracker};
+use crate::ray::Ray;
+use cgmath::{Angle, InnerSpace, Rotation3, SquareMatrix};
 use wgpu::{util::DeviceExt, Buffer};
+
 use winit::{
     dpi::PhysicalPosition,
-    event::{ElementState, MouseButton, MouseScrollDelta, TouchPhase, WindowEvent},
-    keyboard::{Key, NamedKey},
+    event::{MouseScrollDelta, TouchPhase, WindowEvent},
 };
 
+// How quickly transitions close in on their goal, per second
+const EASE_RATE: f32 = 6.0;
+// Slower, for the drifting shots of the tour
+const CINEMATIC_EASE_RATE: f32 = 0.8;
+const TARGET_PER_SECOND: f32 = 5.0;
+const ORBIT_DEGREES_PER_SECOND: f32 = 20.0;
+
+// Named views, picked with the preset actions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    TopDown,
+    Isometric,
+    Front,
+    Orbit,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 4] = [
+        Preset::TopDown,
+        Preset::Isometric,
+        Preset::Front,
+        Preset::Orbit,
+    ];
+
+    // Yaw and elevation of the view. The camera sits on +x at zero yaw, the
+    // layouts grow their history towards -z so the front is at +z.
+    fn angles(self) -> (cgmath::Deg<f32>, cgmath::Deg<f32>) {
+        match self {
+            // Straight down makes look_at degenerate
+            Preset::TopDown => (cgmath::Deg(270.0), cgmath::Deg(89.0)),
+            Preset::Isometric => (cgmath::Deg(315.0), cgmath::Deg(35.264)),
+            Preset::Front => (cgmath::Deg(270.0), cgmath::Deg(10.0)),
+            Preset::Orbit => (cgmath::Deg(315.0), cgmath::Deg(25.0)),
+        }
+    }
+}
+
+impl std::fmt::Display for Preset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Preset::TopDown => "top-down",
+            Preset::Isometric => "isometric",
+            Preset::Front => "front",
+            Preset::Orbit => "orbit",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Where the camera is, as saved between sessions. Angles in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CameraPose {
+    pub angle: f32,
+    pub plane_angle: f32,
+    pub distance: f32,
+    pub target: [f32; 3],
+    // Follow the scene bounds rather than the distance and target above
+    pub auto_fit: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    Perspective,
+    // Straight down at the floor, for the heatmap
+    Orthographic,
+}
+
 pub struct Camera {
+    pub projection: Projection,
     angle: cgmath::Deg<f32>,
     distance: f32,
     target: cgmath::Point3<f32>,
@@ -33,14 +105,48 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
 );
 
 impl Camera {
-    fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        let position = self.target
-            + cgmath::Quaternion::from_angle_y(self.angle)
-                * cgmath::Quaternion::from_angle_z(self.plane_angle)
-                * (cgmath::Vector3::new(1.0, 0.0, 0.0) * self.distance);
+    pub fn position(&self) -> cgmath::Point3<f32> {
+        match self.projection {
+            Projection::Perspective => {
+                self.target
+                    + cgmath::Quaternion::from_angle_y(self.angle)
+                        * cgmath::Quaternion::from_angle_z(self.plane_angle)
+                        * (cgmath::Vector3::new(1.0, 0.0, 0.0) * self.distance)
+            }
+            Projection::Orthographic => self.target + cgmath::Vector3::unit_y() * self.distance,
+        }
+    }
 
-        let view = cgmath::Matrix4::look_at_rh(position, self.target, self.up);
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+    pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        // Keep large fitted scenes inside the far plane
+        let zfar = self.zfar.max(self.distance * 4.0);
+        let (view, proj) = match self.projection {
+            Projection::Perspective => (
+                cgmath::Matrix4::look_at_rh(self.position(), self.target, self.up),
+                cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, zfar),
+            ),
+            Projection::Orthographic => {
+                // Front of the layout (+z) at the bottom of the window. The
+                // distance zooms like it does in perspective.
+                let half_height = self.distance * cgmath::Deg(self.fovy / 2.0).tan();
+                let half_width = half_height * self.aspect;
+                (
+                    cgmath::Matrix4::look_at_rh(
+                        self.position(),
+                        self.target,
+                        -cgmath::Vector3::unit_z(),
+                    ),
+                    cgmath::ortho(
+                        -half_width,
+                        half_width,
+                        -half_height,
+                        half_height,
+                        self.znear,
+                        zfar,
+                    ),
+                )
+            }
+        };
 
         OPENGL_TO_WGPU_MATRIX * proj * view
     }
@@ -75,6 +181,7 @@ impl Camera {
             label: Some("camera_bind_group"),
         });
         let camera = Camera {
+            projection: Projection::Perspective,
             angle: cgmath::Deg(0.0),
             distance: 15.0,
             target: (0.0, 0.0, 0.0).into(),
@@ -97,6 +204,60 @@ impl Camera {
     pub fn resize(&mut self, width: f32, height: f32) {
         self.aspect = width / height;
     }
+
+    // Distance and target that fit the box between `min` and `max` into the
+    // view from any direction, or tightly from above in orthographic mode
+    fn fit(
+        &self,
+        min: cgmath::Point3<f32>,
+        max: cgmath::Point3<f32>,
+    ) -> (f32, cgmath::Point3<f32>) {
+        let center = min + (max - min) / 2.0;
+        let half_fovy = cgmath::Deg(self.fovy / 2.0);
+        if self.projection == Projection::Orthographic {
+            let size = max - min;
+            let half_height = (size.z / 2.0).max(size.x / 2.0 / self.aspect);
+            return (half_height / half_fovy.tan() * 1.05, center);
+        }
+        let radius = (max - min).magnitude() / 2.0;
+        let half_fovx = cgmath::Rad((half_fovy.tan() * self.aspect).atan());
+        let half_fov = cgmath::Rad(cgmath::Rad::from(half_fovy).0.min(half_fovx.0));
+        // A little margin so the edges don't touch the window border
+        (radius / half_fov.sin() * 1.05, center)
+    }
+
+    // Window pixel coordinates of a point in the scene, None when it is
+    // behind the camera or outside the view
+    pub fn project(
+        &self,
+        point: cgmath::Point3<f32>,
+        width: f32,
+        height: f32,
+    ) -> Option<cgmath::Vector2<f32>> {
+        let clip = self.build_view_projection_matrix() * point.to_homogeneous();
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || ndc.z > 1.0 {
+            return None;
+        }
+        Some(cgmath::Vector2::new(
+            (ndc.x + 1.0) / 2.0 * width,
+            (1.0 - ndc.y) / 2.0 * height,
+        ))
+    }
+
+    // Ray from the camera through the given window pixel
+    pub fn ray(&self, pixel: cgmath::Vector2<f32>, width: f32, height: f32) -> Option<Ray> {
+        let inverse = self.build_view_projection_matrix().invert()?;
+        let ndc = cgmath::Vector2::new(pixel.x / width * 2.0 - 1.0, 1.0 - pixel.y / height * 2.0);
+        let unproject = |depth: f32| {
+            let point = inverse * cgmath::Vector4::new(ndc.x, ndc.y, depth, 1.0);
+            cgmath::Point3::from_homogeneous(point)
+        };
+        Some(Ray::between(unproject(0.0), unproject(1.0)))
+    }
 }
 
 #[repr(C)]
@@ -134,9 +295,26 @@ pub struct CameraController {
     is_backward_pressed: bool,
     is_left_pressed: bool,
     is_right_pressed: bool,
-    is_right_mouse_pressed: bool,
+    is_orbit_pressed: bool,
     last_cursor_position: Option<PhysicalPosition<f64>>,
     last_cursor_position_ts: Option<Instant>,
+    // Scene bounds to frame, None until the first update
+    bounds: Option<(cgmath::Point3<f32>, cgmath::Point3<f32>)>,
+    // Follow the scene bounds until the user zooms or moves the target
+    auto_fit: bool,
+    // Yaw and elevation we are easing towards after picking a preset
+    goal_angles: Option<(cgmath::Deg<f32>, cgmath::Deg<f32>)>,
+    // Point and distance to close in on instead of fitting the scene
+    focus: Option<(cgmath::Point3<f32>, f32)>,
+    // Constant turn in degrees per second, overriding the inertia
+    orbit: Option<f32>,
+    ease_rate: f32,
+    gestures: GestureTracker,
+}
+
+// Shortest signed rotation from `from` to `to`
+fn angle_delta(from: cgmath::Deg<f32>, to: cgmath::Deg<f32>) -> cgmath::Deg<f32> {
+    cgmath::Deg((to.0 - from.0 + 540.0).rem_euclid(360.0) - 180.0)
 }
 
 impl CameraController {
@@ -155,12 +333,119 @@ impl CameraController {
             is_backward_pressed: false,
             is_left_pressed: false,
             is_right_pressed: false,
-            is_right_mouse_pressed: false,
+            is_orbit_pressed: false,
             last_cursor_position: None,
             last_cursor_position_ts: None,
+            bounds: None,
+            auto_fit: true,
+            goal_angles: None,
+            focus: None,
+            orbit: None,
+            ease_rate: EASE_RATE,
+            gestures: GestureTracker::default(),
         }
     }
 
+    pub fn configure(&mut self, config: &CameraConfig) {
+        self.distance_per_second = config.distance_per_second;
+        self.distance_per_pixel = config.distance_per_pixel;
+        self.distance_per_line = config.distance_per_line;
+        self.degrees_per_second = config.degrees_per_second;
+        self.degrees_per_pixel = config.degrees_per_pixel;
+    }
+
+    // Called every frame with the current extent of the scene
+    pub fn set_bounds(&mut self, min: cgmath::Point3<f32>, max: cgmath::Point3<f32>) {
+        // Jump straight to the first fit instead of flying in from nowhere
+        if self.bounds.is_none() && self.auto_fit {
+            (self.camera.distance, self.camera.target) = self.camera.fit(min, max);
+        }
+        self.bounds = Some((min, max));
+    }
+
+    pub fn fit(&mut self) {
+        self.auto_fit = true;
+        self.focus = None;
+        self.ease_rate = EASE_RATE;
+    }
+
+    pub fn set_preset(&mut self, preset: Preset) {
+        self.fit();
+        self.goal_angles = Some(preset.angles());
+        self.orbit = (preset == Preset::Orbit).then_some(ORBIT_DEGREES_PER_SECOND);
+        self.angle_velocity = 0.0;
+    }
+
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            angle: self.camera.angle.0,
+            plane_angle: self.camera.plane_angle.0,
+            distance: self.camera.distance,
+            target: self.camera.target.into(),
+            auto_fit: self.auto_fit && self.focus.is_none(),
+        }
+    }
+
+    // Moves smoothly to `pose`, or jumps there when `animate` is false
+    pub fn set_pose(&mut self, pose: CameraPose, animate: bool) {
+        let angles = (
+            cgmath::Deg(pose.angle).normalize(),
+            cgmath::Deg(pose.plane_angle).normalize(),
+        );
+        let target = cgmath::Point3::from(pose.target);
+        self.fit();
+        self.orbit = None;
+        self.angle_velocity = 0.0;
+        self.auto_fit = pose.auto_fit;
+        if animate {
+            self.goal_angles = Some(angles);
+            if !pose.auto_fit {
+                // Eased through the focus, which only applies while fitting
+                self.focus = Some((target, pose.distance));
+                self.auto_fit = true;
+            }
+        } else {
+            (self.camera.angle, self.camera.plane_angle) = angles;
+            self.camera.distance = pose.distance;
+            self.camera.target = target;
+        }
+    }
+
+    // Slowly turn around the scene at the given elevation
+    pub fn orbit(&mut self, plane_angle: cgmath::Deg<f32>, degrees_per_second: f32) {
+        self.goal_angles = Some((self.camera.angle, plane_angle));
+        self.orbit = Some(degrees_per_second);
+        self.ease_rate = CINEMATIC_EASE_RATE;
+    }
+
+    // Drift towards `target`, None drifts back out to the whole scene
+    pub fn focus(&mut self, target: Option<(cgmath::Point3<f32>, f32)>) {
+        self.focus = target;
+        self.auto_fit = true;
+        self.ease_rate = CINEMATIC_EASE_RATE;
+    }
+
+    // Touch and trackpad movement, directly on the camera like a drag
+    fn apply_gesture(&mut self, gesture: Gesture) {
+        if gesture.is_none() {
+            return;
+        }
+        self.goal_angles = None;
+        self.orbit = None;
+        self.angle_velocity = 0.0;
+        if gesture.zoom != 1.0 {
+            self.auto_fit = false;
+            self.focus = None;
+        }
+
+        let camera = &mut self.camera;
+        camera.angle += cgmath::Deg(-gesture.orbit.x * self.degrees_per_pixel + gesture.rotate);
+        camera.angle = camera.angle.normalize();
+        camera.plane_angle += cgmath::Deg(gesture.orbit.y * self.degrees_per_pixel);
+        camera.plane_angle = camera.plane_angle.normalize();
+        camera.distance *= gesture.zoom;
+    }
+
     pub fn camera(&self) -> &Camera {
         &self.camera
     }
@@ -171,6 +456,19 @@ impl CameraController {
 
     pub fn update(&mut self, dt: Duration, queue: &mut wgpu::Queue) {
         let camera = &mut self.camera;
+        if self.is_forward_pressed
+            || self.is_backward_pressed
+            || self.is_up_pressed
+            || self.is_down_pressed
+        {
+            self.auto_fit = false;
+            self.focus = None;
+        }
+        if self.is_left_pressed || self.is_right_pressed {
+            self.goal_angles = None;
+            self.orbit = None;
+        }
+
         if self.is_forward_pressed {
             camera.distance =
                 (camera.distance - self.distance_per_second * dt.as_secs_f32()).max(0.0);
@@ -179,6 +477,13 @@ impl CameraController {
             camera.distance += self.distance_per_second * dt.as_secs_f32();
         }
 
+        if self.is_up_pressed {
+            camera.target.y += TARGET_PER_SECOND * dt.as_secs_f32();
+        }
+        if self.is_down_pressed {
+            camera.target.y -= TARGET_PER_SECOND * dt.as_secs_f32();
+        }
+
         if self.is_right_pressed {
             camera.angle += cgmath::Deg(self.degrees_per_second * dt.as_secs_f32());
             camera.angle = camera.angle.normalize();
@@ -188,7 +493,39 @@ impl CameraController {
             camera.angle = camera.angle.normalize();
         }
 
-        self.angle_velocity *= 0.999;
+        let momentum = self.gestures.momentum(dt);
+        self.apply_gesture(momentum);
+        let camera = &mut self.camera;
+
+        // Ease towards the preset angles and the fitted distance
+        let ease = 1.0 - (-self.ease_rate * dt.as_secs_f32()).exp();
+        if let Some((angle, plane_angle)) = self.goal_angles {
+            let yaw = angle_delta(camera.angle, angle);
+            let pitch = angle_delta(camera.plane_angle, plane_angle);
+            // The orbit keeps turning, only wait for the elevation there
+            let yaw_done = self.orbit.is_some() || yaw.0.abs() < 0.1;
+            if self.orbit.is_none() {
+                camera.angle = (camera.angle + yaw * ease).normalize();
+            }
+            camera.plane_angle = (camera.plane_angle + pitch * ease).normalize();
+            if yaw_done && pitch.0.abs() < 0.1 {
+                self.goal_angles = None;
+            }
+        }
+        let goal = match (self.focus, self.bounds) {
+            (Some((target, distance)), _) => Some((distance, target)),
+            (None, Some((min, max))) => Some(camera.fit(min, max)),
+            (None, None) => None,
+        };
+        if let Some((distance, target)) = goal.filter(|_| self.auto_fit) {
+            camera.distance += (distance - camera.distance) * ease;
+            camera.target += (target - camera.target) * ease;
+        }
+
+        match self.orbit {
+            Some(degrees_per_second) => self.angle_velocity = degrees_per_second,
+            None => self.angle_velocity *= 0.999,
+        }
 
         camera.angle += cgmath::Deg(dt.as_secs_f32() * self.angle_velocity);
         camera.angle = camera.angle.normalize();
@@ -199,54 +536,39 @@ impl CameraController {
         queue.write_buffer(&camera.buffer, 0, bytemuck::cast_slice(&[camera.uniform]));
     }
 
+    // Handles the camera actions, false for any other
+    pub fn action(&mut self, action: Action, pressed: bool) -> bool {
+        match action {
+            Action::CameraUp => self.is_up_pressed = pressed,
+            Action::CameraDown => self.is_down_pressed = pressed,
+            Action::CameraForward => self.is_forward_pressed = pressed,
+            Action::CameraBackward => self.is_backward_pressed = pressed,
+            Action::CameraLeft => self.is_left_pressed = pressed,
+            Action::CameraRight => self.is_right_pressed = pressed,
+            Action::CameraOrbit => self.is_orbit_pressed = pressed,
+            Action::Fit if pressed => self.fit(),
+            Action::PresetTopDown if pressed => self.set_preset(Preset::TopDown),
+            Action::PresetIsometric if pressed => self.set_preset(Preset::Isometric),
+            Action::PresetFront if pressed => self.set_preset(Preset::Front),
+            Action::PresetOrbit if pressed => self.set_preset(Preset::Orbit),
+            _ => return false,
+        }
+        true
+    }
+
     pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        if let Some(gesture) = self.gestures.event(event, Instant::now()) {
+            self.apply_gesture(gesture);
+            return true;
+        }
         match event {
-            WindowEvent::KeyboardInput { event, .. } => {
-                let is_pressed = event.state == ElementState::Pressed;
-                match event.logical_key {
-                    Key::Named(NamedKey::Space) => {
-                        self.is_up_pressed = is_pressed;
-                        true
-                    }
-                    Key::Named(NamedKey::Shift) => {
-                        self.is_down_pressed = is_pressed;
-                        true
-                    }
-                    Key::Named(NamedKey::ArrowUp) => {
-                        self.is_forward_pressed = is_pressed;
-                        true
-                    }
-                    Key::Named(NamedKey::ArrowLeft) => {
-                        self.is_left_pressed = is_pressed;
-                        true
-                    }
-                    Key::Named(NamedKey::ArrowDown) => {
-                        self.is_backward_pressed = is_pressed;
-                        true
-                    }
-                    Key::Named(NamedKey::ArrowRight) => {
-                        self.is_right_pressed = is_pressed;
-                        true
-                    }
-                    _ => false,
-                }
-            }
-            WindowEvent::MouseInput {
-                device_id: _,
-                state,
-                button: MouseButton::Right,
-            } => {
-                self.is_right_mouse_pressed = match state {
-                    ElementState::Pressed => true,
-                    ElementState::Released => false,
-                };
-                true
-            }
             WindowEvent::MouseWheel {
                 device_id: _,
                 delta,
                 phase: TouchPhase::Moved,
             } => {
+                self.auto_fit = false;
+                self.focus = None;
                 self.camera.distance += -match delta {
                     MouseScrollDelta::LineDelta(_, delta) => delta * self.distance_per_line,
                     MouseScrollDelta::PixelDelta(PhysicalPosition { x: _, y }) => {
@@ -264,7 +586,7 @@ impl CameraController {
                 device_id: _,
                 position,
             } => {
-                if self.is_right_mouse_pressed {
+                if self.is_orbit_pressed {
                     if let Some(last_cursor_position) = self.last_cursor_position {
                         let delta = cgmath::Vector2::new(position.x as f32, position.y as f32)
                             - cgmath::Vector2::new(
@@ -272,6 +594,8 @@ impl CameraController {
                                 last_cursor_position.y as f32,
                             );
 
+                        self.goal_angles = None;
+                        self.orbit = None;
                         self.camera.plane_angle += cgmath::Deg(delta.y * self.degrees_per_pixel);
                         self.camera.plane_angle = self.camera.plane_angle.normalize();
 
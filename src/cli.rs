@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+
+use crate::cpu::CpuSelection;
+use crate::layout::Layout;
+use crate::metrics::Colormap;
+use crate::synthetic::Scenario;
+
+const USAGE: &str = "\
+Usage: lolitop [OPTIONS]
+
+Options:
+  -r, --sample-rate <HZ>    How often to sample the metrics, per second
+  -c, --colormap <NAME>     Bar colors: fire, rainbow, cosmic or white
+  -l, --layout <NAME>       grid, ring, spiral, row, honeycomb or a custom layout
+  -f, --fullscreen          Start fullscreen
+      --no-fullscreen       Start windowed, even if the last session was fullscreen
+  -t, --transparent         Start with a transparent background
+      --no-transparent      Start with an opaque background
+      --undecorated         Start without window decorations (default)
+      --decorated           Start with window decorations
+  -s, --size <WxH>          Window size in pixels, like 1280x720
+  -p, --position <X,Y>      Window position in pixels
+  -m, --metrics <LIST>      Rows of /proc/stat to show, comma separated: total,
+                            cores, or single cores and ranges like cpu3 or cpu0-7
+                            [default: cores]
+      --screensaver <SECONDS>
+                            Start the fullscreen tour after this long without input
+      --replay <PATH>       Play a recording back instead of sampling this machine
+      --speed <FACTOR>      Replay speed [default: 1]
+      --loop                Start the replay over when it ends
+      --synthetic <NAME>    Show made up load instead of this machine: sine, spikes,
+                            pegged, fork-bomb or smt
+      --cores <N>           Cores to make up for --synthetic [default: 16]
+      --record <PATH>       Record every sample to this file from the start
+      --config <PATH>       Config file [default: $XDG_CONFIG_HOME/lolitop/config.toml]
+      --proc-root <PATH>    Where procfs is mounted [default: /proc]
+      --backend <NAME>      Graphics backend: vulkan, gl, metal, dx12 or all
+                            [default: all]
+  -h, --help                Print help
+  -V, --version             Print version
+";
+
+// Startup options. Anything left at None keeps the value restored from the
+// last session, or the built-in default.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub sample_rate_hz: Option<f32>,
+    pub colormap: Option<Colormap>,
+    pub layout: Option<String>,
+    pub fullscreen: Option<bool>,
+    pub transparent: Option<bool>,
+    pub decorated: bool,
+    pub size: Option<(u32, u32)>,
+    pub position: Option<(i32, i32)>,
+    pub metrics: Option<CpuSelection>,
+    pub screensaver: Option<Duration>,
+    pub replay: Option<PathBuf>,
+    pub speed: f32,
+    pub looping: bool,
+    pub synthetic: Option<Scenario>,
+    pub cores: usize,
+    pub record: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub proc_root: PathBuf,
+    pub backends: wgpu::Backends,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            sample_rate_hz: None,
+            colormap: None,
+            layout: None,
+            fullscreen: None,
+            transparent: None,
+            decorated: false,
+            size: None,
+            position: None,
+            metrics: None,
+            screensaver: None,
+            replay: None,
+            speed: 1.0,
+            looping: false,
+            synthetic: None,
+            cores: 16,
+            record: None,
+            config: None,
+            proc_root: PathBuf::from("/proc"),
+            backends: wgpu::Backends::all(),
+        }
+    }
+}
+
+// Parses the arguments after the program name. Returns None when the help or
+// the version was asked for and printed.
+pub fn parse(args: impl IntoIterator<Item = String>) -> anyhow::Result<Option<Options>> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // Both `--option value` and `--option=value`
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => {
+                (name.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .with_context(|| format!("{} needs a value", name))
+        };
+        match name.as_str() {
+            "-r" | "--sample-rate" => {
+                let rate = value()?;
+                let rate: f32 = rate
+                    .parse()
+                    .with_context(|| format!("invalid sample rate '{}'", rate))?;
+                if !(rate > 0.0 && rate.is_finite()) {
+                    bail!("the sample rate must be above zero");
+                }
+                options.sample_rate_hz = Some(rate);
+            }
+            "-c" | "--colormap" => options.colormap = Some(value()?.parse()?),
+            "-l" | "--layout" => options.layout = Some(value()?),
+            "-f" | "--fullscreen" => options.fullscreen = Some(true),
+            "--no-fullscreen" => options.fullscreen = Some(false),
+            "-t" | "--transparent" => options.transparent = Some(true),
+            "--no-transparent" => options.transparent = Some(false),
+            "--undecorated" => options.decorated = false,
+            "--decorated" => options.decorated = true,
+            "-s" | "--size" => {
+                let size = value()?;
+                options.size = Some(
+                    pair(&size, 'x')
+                        .filter(|&(w, h)| w > 0 && h > 0)
+                        .with_context(|| format!("invalid size '{}', expected WxH", size))?,
+                );
+            }
+            "-p" | "--position" => {
+                let position = value()?;
+                options.position =
+                    Some(pair(&position, ',').with_context(|| {
+                        format!("invalid position '{}', expected X,Y", position)
+                    })?);
+            }
+            "-m" | "--metrics" => options.metrics = Some(value()?.parse()?),
+            "--screensaver" => {
+                let seconds = value()?;
+                let seconds: f32 = seconds
+                    .parse()
+                    .with_context(|| format!("invalid screensaver timeout '{}'", seconds))?;
+                options.screensaver = Some(
+                    Duration::try_from_secs_f32(seconds)
+                        .context("the screensaver timeout can't be negative")?,
+                );
+            }
+            "--replay" => options.replay = Some(PathBuf::from(value()?)),
+            "--speed" => {
+                let speed = value()?;
+                options.speed = speed
+                    .parse()
+                    .with_context(|| format!("invalid replay speed '{}'", speed))?;
+                if !(options.speed > 0.0 && options.speed.is_finite()) {
+                    bail!("the replay speed must be above zero");
+                }
+            }
+            "--loop" => options.looping = true,
+            "--synthetic" => options.synthetic = Some(value()?.parse()?),
+            "--cores" => {
+                let cores = value()?;
+                options.cores = cores
+                    .parse()
+                    .ok()
+                    .filter(|&cores| cores > 0)
+                    .with_context(|| format!("invalid core count '{}'", cores))?;
+            }
+            "--record" => options.record = Some(PathBuf::from(value()?)),
+            "--config" => options.config = Some(PathBuf::from(value()?)),
+            "--proc-root" => options.proc_root = PathBuf::from(value()?),
+            "--backend" => {
+                let backend = value()?;
+                options.backends = match backend.as_str() {
+                    "vulkan" => wgpu::Backends::VULKAN,
+                    "gl" => wgpu::Backends::GL,
+                    "metal" => wgpu::Backends::METAL,
+                    "dx12" => wgpu::Backends::DX12,
+                    "all" => wgpu::Backends::all(),
+                    _ => bail!("unknown backend '{}'", backend),
+                };
+            }
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return Ok(None);
+            }
+            "-V" | "--version" => {
+                println!("lolitop {}", env!("CARGO_PKG_VERSION"));
+                return Ok(None);
+            }
+            _ => bail!("unexpected argument '{}'", arg),
+        }
+    }
+
+    // Fail here rather than in the middle of opening the window
+    if let Some(name) = &options.layout {
+        let layouts = Layout::available();
+        if !layouts.iter().any(|layout| layout.to_string() == *name) {
+            let names = layouts.iter().map(Layout::to_string).collect::<Vec<_>>();
+            bail!(
+                "unknown layout '{}', expected one of {}",
+                name,
+                names.join(", ")
+            );
+        }
+    }
+    if options.replay.is_some() && options.synthetic.is_some() {
+        bail!("--replay and --synthetic can't be used together");
+    }
+    // Neither a replay nor made up load needs procfs
+    let needed = match &options.replay {
+        Some(path) => Some(path.clone()),
+        None if options.synthetic.is_some() => None,
+        None => Some(options.proc_root.join("stat")),
+    };
+    if let Some(needed) = needed {
+        std::fs::metadata(&needed).with_context(|| format!("cannot read {}", needed.display()))?;
+    }
+    Ok(Some(options))
+}
+
+fn pair<T: std::str::FromStr>(value: &str, separator: char) -> Option<(T, T)> {
+    let (a, b) = value.split_once(separator)?;
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
@@ -0,0 +1,475 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{bail, ensure, Context};
+use serde::Deserialize;
+
+use crate::bindings::Bindings;
+use crate::cpu::CpuSelection;
+use crate::ground::GroundMode;
+use crate::metrics::{Colormap, SPACING};
+use crate::paths;
+use crate::sparklines::SparklineLayout;
+
+// How often the config file is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Everything tunable, read from $XDG_CONFIG_HOME/lolitop/config.toml. Every
+// section and key is optional and falls back to the values below.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub sampling: SamplingConfig,
+    pub render: RenderConfig,
+    pub text: TextConfig,
+    pub layout: LayoutConfig,
+    pub camera: CameraConfig,
+    pub tour: TourConfig,
+    pub effects: EffectsConfig,
+    pub recording: RecordingConfig,
+    // Group names like `cpu` to what their bars show, see encoding.rs
+    pub encoding: BTreeMap<String, EncodingConfig>,
+    // Action names to the keys and buttons bound to them, see bindings.rs
+    pub bindings: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SamplingConfig {
+    // Rates the sample rate key cycles through, per second
+    pub rates: Vec<f32>,
+    pub rate: Option<f32>,
+    // Rows of /proc/stat to show, like --metrics
+    pub metrics: Option<CpuSelection>,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        SamplingConfig {
+            rates: vec![0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0],
+            rate: None,
+            metrics: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderConfig {
+    pub msaa: u32,
+    pub colormap: Option<Colormap>,
+    pub light_color: [f32; 3],
+    // Degrees per second the light circles the scene
+    pub light_rotation: f32,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig {
+            msaa: 4,
+            colormap: None,
+            light_color: [1.0, 1.0, 1.0],
+            light_rotation: 60.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TextConfig {
+    pub size: f32,
+    pub scale: f32,
+}
+
+impl Default for TextConfig {
+    fn default() -> Self {
+        TextConfig {
+            size: 18.0,
+            scale: 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutConfig {
+    pub name: Option<String>,
+    pub spacing: f32,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        LayoutConfig {
+            name: None,
+            spacing: SPACING,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    pub distance_per_second: f32,
+    pub distance_per_pixel: f32,
+    pub distance_per_line: f32,
+    pub degrees_per_second: f32,
+    pub degrees_per_pixel: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            distance_per_second: 15.0,
+            distance_per_pixel: 0.1,
+            distance_per_line: 1.0,
+            degrees_per_second: 180.0,
+            degrees_per_pixel: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TourConfig {
+    // Seconds without input before the screensaver starts, never when unset
+    pub screensaver: Option<f32>,
+}
+
+// Overlays and helpers, unset ones keep their built-in defaults
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EffectsConfig {
+    pub ground: Option<GroundMode>,
+    pub labels: Option<bool>,
+    pub sparklines: Option<SparklineLayout>,
+    pub waterfall: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordingConfig {
+    // Where the record key writes to, a new file under
+    // $XDG_DATA_HOME/lolitop/recordings each time when unset
+    pub path: Option<PathBuf>,
+    // Megabytes per file before it is rotated
+    pub max_size: f32,
+    // Rotated files kept next to the one being written
+    pub max_files: usize,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        RecordingConfig {
+            path: None,
+            max_size: 64.0,
+            max_files: 4,
+        }
+    }
+}
+
+// Metric channels bound to the visual channels of a group's bars, like
+// `color = "temperature"`. Unset ones keep the built-in encoding.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncodingConfig {
+    pub height: Option<ChannelBinding>,
+    pub color: Option<ChannelBinding>,
+    pub width: Option<ChannelBinding>,
+    pub glow: Option<ChannelBinding>,
+}
+
+// A channel by name, or with the range that maps to 0 and 1 as in
+// `{ channel = "temperature", min = 40, max = 95 }`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "ChannelBindingValue")]
+pub struct ChannelBinding {
+    pub channel: String,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(
+    untagged,
+    deny_unknown_fields,
+    expecting = "a channel name or a table of channel, min and max"
+)]
+enum ChannelBindingValue {
+    Name(String),
+    Table {
+        channel: String,
+        min: Option<f32>,
+        max: Option<f32>,
+    },
+}
+
+impl From<ChannelBindingValue> for ChannelBinding {
+    fn from(value: ChannelBindingValue) -> Self {
+        match value {
+            ChannelBindingValue::Name(channel) => ChannelBinding {
+                channel,
+                min: None,
+                max: None,
+            },
+            ChannelBindingValue::Table { channel, min, max } => {
+                ChannelBinding { channel, min, max }
+            }
+        }
+    }
+}
+
+impl RecordingConfig {
+    pub fn max_bytes(&self) -> u64 {
+        (self.max_size as f64 * 1_000_000.0) as u64
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        paths::config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    // A missing file is the same as an empty one, unless it is `required`
+    pub fn load(path: &Path, required: bool) -> anyhow::Result<Config> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => {
+                return Ok(Config::default())
+            }
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        let mut config: Config = toml::from_str(&contents)
+            .with_context(|| format!("invalid config {}", path.display()))?;
+        config
+            .validate()
+            .with_context(|| format!("invalid config {}", path.display()))?;
+        config.sampling.rates.sort_by(f32::total_cmp);
+        config.sampling.rates.dedup();
+        Ok(config)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let rates = &self.sampling.rates;
+        ensure!(!rates.is_empty(), "sampling.rates must not be empty");
+        for rate in rates {
+            ensure!(
+                rate.is_finite() && *rate > 0.0,
+                "sampling.rates must be above zero, got {}",
+                rate
+            );
+        }
+
+        if let Some(rate) = self.sampling.rate {
+            ensure!(
+                rate.is_finite() && rate > 0.0,
+                "sampling.rate must be above zero, got {}",
+                rate
+            );
+        }
+
+        if ![1, 2, 4, 8].contains(&self.render.msaa) {
+            bail!("render.msaa must be 1, 2, 4 or 8, got {}", self.render.msaa);
+        }
+        for channel in self.render.light_color {
+            ensure!(
+                (0.0..=10.0).contains(&channel),
+                "render.light_color channels must be between 0 and 10, got {}",
+                channel
+            );
+        }
+        ensure!(
+            self.render.light_rotation.is_finite(),
+            "render.light_rotation must be a number, got {}",
+            self.render.light_rotation
+        );
+
+        ensure!(
+            (4.0..=200.0).contains(&self.text.size),
+            "text.size must be between 4 and 200, got {}",
+            self.text.size
+        );
+        ensure!(
+            (0.25..=8.0).contains(&self.text.scale),
+            "text.scale must be between 0.25 and 8, got {}",
+            self.text.scale
+        );
+
+        // Bars are 2 wide, any closer and they overlap
+        ensure!(
+            (2.0..=100.0).contains(&self.layout.spacing),
+            "layout.spacing must be between 2 and 100, got {}",
+            self.layout.spacing
+        );
+
+        let camera = &self.camera;
+        for (name, value) in [
+            ("distance_per_second", camera.distance_per_second),
+            ("distance_per_pixel", camera.distance_per_pixel),
+            ("distance_per_line", camera.distance_per_line),
+            ("degrees_per_second", camera.degrees_per_second),
+            ("degrees_per_pixel", camera.degrees_per_pixel),
+        ] {
+            ensure!(
+                value.is_finite() && value >= 0.0,
+                "camera.{} must not be negative, got {}",
+                name,
+                value
+            );
+        }
+
+        if let Some(seconds) = self.tour.screensaver {
+            ensure!(
+                seconds.is_finite() && seconds >= 0.0,
+                "tour.screensaver must not be negative, got {}",
+                seconds
+            );
+        }
+
+        ensure!(
+            self.recording.max_size.is_finite() && self.recording.max_size > 0.0,
+            "recording.max_size must be above zero, got {}",
+            self.recording.max_size
+        );
+
+        // Groups and their channels depend on the sources, they are checked
+        // once those are known
+        for (group, encoding) in &self.encoding {
+            for (visual, binding) in [
+                ("height", &encoding.height),
+                ("color", &encoding.color),
+                ("width", &encoding.width),
+                ("glow", &encoding.glow),
+            ] {
+                let Some(ChannelBinding { min, max, .. }) = binding else {
+                    continue;
+                };
+                for bound in [min, max].into_iter().flatten() {
+                    ensure!(
+                        bound.is_finite(),
+                        "encoding.{}.{} range must be numbers, got {}",
+                        group,
+                        visual,
+                        bound
+                    );
+                }
+                if let (Some(min), Some(max)) = (min, max) {
+                    ensure!(
+                        min < max,
+                        "encoding.{}.{} min must be below max, got {} and {}",
+                        group,
+                        visual,
+                        min,
+                        max
+                    );
+                }
+            }
+        }
+
+        Bindings::new(&self.bindings)?;
+        Ok(())
+    }
+
+    pub fn screensaver(&self) -> Option<Duration> {
+        self.tour.screensaver.map(Duration::from_secs_f32)
+    }
+
+    // f32 as f64 writes 0.85 as 0.8500000238418579
+    pub fn float(value: f32) -> toml_edit::Value {
+        value
+            .to_string()
+            .parse::<f64>()
+            .unwrap_or(value as f64)
+            .into()
+    }
+
+    // Writes one setting, like `render.colormap`, into the config file. The
+    // rest of the file is kept as it is, comments included.
+    pub fn save_setting(
+        path: &Path,
+        key: &str,
+        value: impl Into<toml_edit::Value>,
+    ) -> anyhow::Result<()> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        let mut document = contents
+            .parse::<toml_edit::DocumentMut>()
+            .with_context(|| format!("invalid config {}", path.display()))?;
+        let (section, name) = key.split_once('.').unwrap();
+        let table = document
+            .entry(section)
+            .or_insert(toml_edit::table())
+            .as_table_like_mut()
+            .with_context(|| format!("{} in {} is not a table", section, path.display()))?;
+        table.insert(name, toml_edit::value(value));
+
+        let dir = path.parent().unwrap();
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        // Write next to it and rename so a crash never leaves half a file
+        let temporary = path.with_extension("toml.tmp");
+        fs::write(&temporary, document.to_string())
+            .with_context(|| format!("failed to write {}", temporary.display()))?;
+        fs::rename(&temporary, path)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+// Reloads the config when the file changes. Polling the modification time
+// once a second is plenty for a file edited by hand.
+pub struct ConfigWatcher {
+    path: Option<PathBuf>,
+    // Named on the command line rather than the default location, so it has
+    // to be there
+    required: bool,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: Option<PathBuf>, required: bool) -> Self {
+        let modified = path.as_deref().and_then(modified);
+        ConfigWatcher {
+            path,
+            required,
+            modified,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // The config as it is now, for the first load
+    pub fn load(&self) -> anyhow::Result<Config> {
+        match &self.path {
+            Some(path) => Config::load(path, self.required),
+            None => Ok(Config::default()),
+        }
+    }
+
+    // The freshly loaded config when the file changed since the last call
+    pub fn poll(&mut self) -> Option<anyhow::Result<Config>> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+        let modified = self.path.as_deref().and_then(modified);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(self.load())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
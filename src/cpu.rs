@@ -1,9 +1,51 @@
 use std::{
-    collections::HashMap,
-    fs::File,
-    io::{BufRead, BufReader},
+    any::Any,
+    collections::VecDeque,
+    fmt, fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
 };
 
+use anyhow::{bail, Context};
+
+use crate::source::{Channel, CounterSource, Counters, MetricSource, ProcStat, Row, SharedReplay};
+
+// Number of usage values kept per core
+pub const HISTORY_LEN: usize = 600;
+// Clocks and temperatures change slowly and take a file read per core
+const SENSOR_INTERVAL: Duration = Duration::from_secs(1);
+
+const CHANNELS: [Channel; 5] = [
+    Channel {
+        name: "usage",
+        unit: "%",
+        range: Some((0.0, 100.0)),
+    },
+    // Relative to the fastest core
+    Channel {
+        name: "clock",
+        unit: "GHz",
+        range: None,
+    },
+    Channel {
+        name: "temperature",
+        unit: "°C",
+        range: Some((30.0, 100.0)),
+    },
+    Channel {
+        name: "iowait",
+        unit: "%",
+        range: Some((0.0, 100.0)),
+    },
+    Channel {
+        name: "steal",
+        unit: "%",
+        range: Some((0.0, 100.0)),
+    },
+];
+
 #[derive(Debug)]
 struct CPUSample {
     cpu_id: String,
@@ -20,6 +62,22 @@ struct CPUSample {
 }
 
 impl CPUSample {
+    fn new(cpu_id: String, counters: Counters) -> Self {
+        let [user, nice, system, idle, iowait, irq, softirq, steal, guest, guest_nice] = counters;
+        CPUSample {
+            cpu_id,
+            user,
+            nice,
+            system,
+            idle,
+            iowait,
+            irq,
+            softirq,
+            steal,
+            guest,
+            guest_nice,
+        }
+    }
     fn total(&self) -> u64 {
         self.user
             + self.nice
@@ -40,82 +98,437 @@ impl CPUSample {
         let other_total = other.total();
         let idle = self.idle();
         let other_idle = other.idle();
-        let total_delta = total - other_total;
-        let idle_delta = idle - other_idle;
+        // Counters can go backwards when a core is taken offline
+        let total_delta = total.saturating_sub(other_total);
+        let idle_delta = idle.saturating_sub(other_idle);
+        if total_delta == 0 {
+            return 0.0;
+        }
         1.0 - (idle_delta as f32 / total_delta as f32)
     }
 }
 
-fn parse_row(row: &str) -> Option<CPUSample> {
-    let words: Vec<&str> = row.split_whitespace().collect();
-    // The line we are interested in is the one that starts with cpu
-    if !words[0].starts_with("cpu") || words[0] == "cpu" {
-        return None;
+// Share of the time since the previous sample spent in each state
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Breakdown {
+    pub user: f32,
+    pub nice: f32,
+    pub system: f32,
+    pub idle: f32,
+    pub iowait: f32,
+    pub irq: f32,
+    pub softirq: f32,
+    pub steal: f32,
+}
+
+impl CPUSample {
+    fn breakdown(&self, other: &Self) -> Breakdown {
+        let total = self.total().saturating_sub(other.total()).max(1) as f32;
+        let share = |a: u64, b: u64| a.saturating_sub(b) as f32 / total;
+        Breakdown {
+            user: share(self.user, other.user),
+            nice: share(self.nice, other.nice),
+            system: share(self.system, other.system),
+            idle: share(self.idle, other.idle),
+            iowait: share(self.iowait, other.iowait),
+            irq: share(self.irq, other.irq),
+            softirq: share(self.softirq, other.softirq),
+            steal: share(self.steal, other.steal),
+        }
     }
+}
 
-    Some(CPUSample {
-        cpu_id: words[0].to_string(),
-        user: words[1].parse().ok()?,
-        nice: words[2].parse().ok()?,
-        system: words[3].parse().ok()?,
-        idle: words[4].parse().ok()?,
-        iowait: words[5].parse().ok()?,
-        irq: words[6].parse().ok()?,
-        softirq: words[7].parse().ok()?,
-        steal: words[8].parse().ok()?,
-        guest: words[9].parse().ok()?,
-        guest_nice: words[10].parse().ok()?,
-    })
+// Current clock of a core such as `cpu3`, from cpufreq
+pub fn frequency_mhz(cpu_id: &str) -> Option<f32> {
+    let path = format!(
+        "/sys/devices/system/cpu/{}/cpufreq/scaling_cur_freq",
+        cpu_id
+    );
+    let khz: f32 = std::fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    Some(khz / 1000.0)
+}
+
+// The hwmon input with the temperature of the physical core a core such as
+// `cpu3` runs on. coretemp has one per core, k10temp only one for the package.
+fn temperature_sensor(cpu_id: &str) -> Option<PathBuf> {
+    let topology = format!("/sys/devices/system/cpu/{}/topology/core_id", cpu_id);
+    let core_label = format!("Core {}", fs::read_to_string(topology).ok()?.trim());
+    let mut package = None;
+    for hwmon in fs::read_dir("/sys/class/hwmon").ok()?.flatten() {
+        let dir = hwmon.path();
+        let name = fs::read_to_string(dir.join("name")).unwrap_or_default();
+        if !matches!(name.trim(), "coretemp" | "k10temp" | "zenpower") {
+            continue;
+        }
+        for entry in fs::read_dir(&dir).ok()?.flatten() {
+            let file_name = entry.file_name();
+            let Some(sensor) = file_name
+                .to_str()
+                .and_then(|name| name.strip_suffix("_label"))
+            else {
+                continue;
+            };
+            if fs::read_to_string(entry.path()).is_ok_and(|label| label.trim() == core_label) {
+                return Some(dir.join(format!("{}_input", sensor)));
+            }
+        }
+        package.get_or_insert(dir.join("temp1_input"));
+    }
+    package
+}
+
+fn temperature_celsius(sensor: &Path) -> Option<f32> {
+    let millidegrees: f32 = fs::read_to_string(sensor).ok()?.trim().parse().ok()?;
+    Some(millidegrees / 1000.0)
+}
+
+struct Core {
+    id: String,
+    // The last few raw samples, enough to interpolate between the last two usages
+    samples: VecDeque<CPUSample>,
+    history: VecDeque<f32>,
+    temperature_sensor: Option<PathBuf>,
+    frequency_mhz: Option<f32>,
+    temperature: Option<f32>,
+}
+
+// Which rows of /proc/stat to show, parsed from a list like `total,cpu0-3,cpu8`.
+// `cores` stands for every core.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct CpuSelection {
+    pub total: bool,
+    pub all_cores: bool,
+    pub cores: Vec<RangeInclusive<usize>>,
+}
+
+impl Default for CpuSelection {
+    fn default() -> Self {
+        CpuSelection {
+            total: false,
+            all_cores: true,
+            cores: Vec::new(),
+        }
+    }
+}
+
+impl CpuSelection {
+    fn shows(&self, cpu_id: &str) -> bool {
+        if cpu_id == "cpu" {
+            return self.total;
+        }
+        let Ok(number) = cpu_id.trim_start_matches("cpu").parse::<usize>() else {
+            return false;
+        };
+        self.all_cores || self.cores.iter().any(|range| range.contains(&number))
+    }
+}
+
+impl FromStr for CpuSelection {
+    type Err = anyhow::Error;
+
+    fn from_str(list: &str) -> anyhow::Result<Self> {
+        let mut selection = CpuSelection {
+            total: false,
+            all_cores: false,
+            cores: Vec::new(),
+        };
+        for item in list.split(',').map(str::trim) {
+            match item {
+                "total" => selection.total = true,
+                "cores" => selection.all_cores = true,
+                _ => {
+                    let Some(cores) = item.strip_prefix("cpu") else {
+                        bail!(
+                            "unknown metric '{}', expected total, cores or cpuN[-M]",
+                            item
+                        );
+                    };
+                    let number = |n: &str| {
+                        n.parse::<usize>()
+                            .with_context(|| format!("invalid core number in '{}'", item))
+                    };
+                    let range = match cores.split_once('-') {
+                        Some((first, last)) => number(first)?..=number(last)?,
+                        None => number(cores)?..=number(cores)?,
+                    };
+                    if range.is_empty() {
+                        bail!("empty core range '{}'", item);
+                    }
+                    selection.cores.push(range);
+                }
+            }
+        }
+        Ok(selection)
+    }
+}
+
+impl TryFrom<String> for CpuSelection {
+    type Error = anyhow::Error;
+
+    fn try_from(list: String) -> anyhow::Result<Self> {
+        list.parse()
+    }
+}
+
+impl fmt::Display for CpuSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut items = vec![];
+        if self.total {
+            items.push("total".to_string());
+        }
+        if self.all_cores {
+            items.push("cores".to_string());
+        }
+        for range in &self.cores {
+            if range.start() == range.end() {
+                items.push(format!("cpu{}", range.start()));
+            } else {
+                items.push(format!("cpu{}-{}", range.start(), range.end()));
+            }
+        }
+        write!(f, "{}", items.join(","))
+    }
 }
 
 pub struct CPUMetrics {
-    samples: HashMap<String, Vec<CPUSample>>,
+    // In /proc/stat order
+    cores: Vec<Core>,
+    proc_root: PathBuf,
+    source: Box<dyn CounterSource>,
+    selection: CpuSelection,
+    // Every row of the newest sample whatever the selection, for recording
+    rows: Vec<Row>,
+    last_sensors: Option<Instant>,
 }
 
 impl CPUMetrics {
+    pub fn new(
+        proc_root: PathBuf,
+        source: Box<dyn CounterSource>,
+        selection: CpuSelection,
+    ) -> Self {
+        let mut s = CPUMetrics {
+            cores: Vec::new(),
+            proc_root,
+            source,
+            selection,
+            rows: Vec::new(),
+            last_sensors: None,
+        };
+        s.sample();
+        s
+    }
+
+    // Starts over with other rows from the same source
+    pub fn set_selection(&mut self, selection: CpuSelection) {
+        self.selection = selection;
+        self.cores.clear();
+        self.last_sensors = None;
+        self.sample();
+    }
+
+    pub fn selection(&self) -> &CpuSelection {
+        &self.selection
+    }
+
+    // Where procfs is mounted, /proc unless pointed at a copy or a container
+    pub fn proc_root(&self) -> &Path {
+        &self.proc_root
+    }
+
+    // Whether the counters are this machine's, see `CounterSource::is_live`
+    pub fn is_live(&self) -> bool {
+        self.source.is_live()
+    }
+
     pub fn ncpus(&self) -> usize {
-        self.samples.len()
-    }
-
-    pub fn sample(&mut self) {
-        // parse row for each line in /proc/stat
-        let file = File::open("/proc/stat").unwrap();
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let line = line.unwrap();
-            let sample = parse_row(&line);
-            if let Some(sample) = sample {
-                self.samples
-                    .entry(sample.cpu_id.clone())
-                    .or_default()
-                    .push(sample);
+        self.cores.len()
+    }
+
+    pub fn cpu_ids(&self) -> impl Iterator<Item = &str> {
+        self.cores.iter().map(|core| core.id.as_str())
+    }
+
+    pub fn breakdown(&self, cpu: usize) -> Option<Breakdown> {
+        let samples = &self.cores.get(cpu)?.samples;
+        if samples.len() < 2 {
+            return None;
+        }
+        Some(samples[samples.len() - 1].breakdown(&samples[samples.len() - 2]))
+    }
+
+    // Clocks and temperatures, only when the counters are from this machine
+    fn read_sensors(&mut self) {
+        let now = Instant::now();
+        if !self.source.is_live()
+            || self
+                .last_sensors
+                .is_some_and(|last| now - last < SENSOR_INTERVAL)
+        {
+            return;
+        }
+        self.last_sensors = Some(now);
+        for core in &mut self.cores {
+            core.frequency_mhz = frequency_mhz(&core.id);
+            core.temperature = core
+                .temperature_sensor
+                .as_deref()
+                .and_then(temperature_celsius);
+        }
+    }
+
+    fn push(&mut self, sample: CPUSample) {
+        let index = match self.cores.iter().position(|core| core.id == sample.cpu_id) {
+            Some(index) => index,
+            None => {
+                let temperature_sensor = if self.source.is_live() {
+                    temperature_sensor(&sample.cpu_id)
+                } else {
+                    None
+                };
+                self.cores.push(Core {
+                    id: sample.cpu_id.clone(),
+                    samples: VecDeque::new(),
+                    history: VecDeque::new(),
+                    temperature_sensor,
+                    frequency_mhz: None,
+                    temperature: None,
+                });
+                self.cores.len() - 1
+            }
+        };
+        let core = &mut self.cores[index];
+
+        if let Some(prev) = core.samples.back() {
+            if core.history.len() == HISTORY_LEN {
+                core.history.pop_front();
             }
+            core.history.push_back(sample.usage(prev));
         }
+        if core.samples.len() == 3 {
+            core.samples.pop_front();
+        }
+        core.samples.push_back(sample);
+    }
+}
+
+impl MetricSource for CPUMetrics {
+    fn name(&self) -> &str {
+        "cpu"
+    }
+
+    fn channels(&self) -> &[Channel] {
+        &CHANNELS
+    }
+
+    fn instances(&self) -> Vec<&str> {
+        self.cpu_ids().collect()
     }
 
-    pub fn interpolate_usage(&self, delta: f32) -> Vec<f32> {
-        self.samples.iter().filter_map(|(_, samples)| {
-            if samples.len() < 3 {
-                return None;
+    fn sample(&mut self) {
+        let rows = match self.source.sample() {
+            Ok(rows) => rows,
+            Err(e) => {
+                log::error!("{:#}", e);
+                return;
             }
-            let last = samples.last().unwrap();
-            let prev = &samples[samples.len() - 2];
-            let pprev = &samples[samples.len() - 3];
+        };
+        for (cpu_id, counters) in &rows {
+            // Like the `meminfo` row of a replay
+            let Ok(counters) = Counters::try_from(counters.as_slice()) else {
+                continue;
+            };
+            if self.selection.shows(cpu_id) {
+                self.push(CPUSample::new(cpu_id.clone(), counters));
+            }
+        }
+        // A replay has the rows of the other sources too
+        self.rows = rows
+            .into_iter()
+            .filter(|(cpu_id, _)| cpu_id.starts_with("cpu"))
+            .collect();
+        self.read_sensors();
+    }
+
+    // Cores with fewer than three samples stay at zero
+    fn values(&self, delta: f32) -> Vec<f32> {
+        self.cores
+            .iter()
+            .map(|core| {
+                let samples = &core.samples;
+                if samples.len() < 3 {
+                    return 0.0;
+                }
+                let last = &samples[samples.len() - 1];
+                let prev = &samples[samples.len() - 2];
+                let pprev = &samples[samples.len() - 3];
+
+                let last_usage = last.usage(prev);
+                let prev_usage = prev.usage(pprev);
+                prev_usage + (last_usage - prev_usage) * delta
+            })
+            .collect()
+    }
+
+    fn history(&self, instance: usize) -> &VecDeque<f32> {
+        &self.cores[instance].history
+    }
+
+    fn value(&self, instance: usize, channel: usize) -> Option<f32> {
+        let core = self.cores.get(instance)?;
+        match channel {
+            0 => Some(core.history.back()? * 100.0),
+            1 => core.frequency_mhz.map(|mhz| mhz / 1000.0),
+            2 => core.temperature,
+            3 => Some(self.breakdown(instance)?.iowait * 100.0),
+            4 => Some(self.breakdown(instance)?.steal * 100.0),
+            _ => None,
+        }
+    }
+
+    fn details(&self, instance: usize) -> Vec<String> {
+        let Some(b) = self.breakdown(instance) else {
+            return vec![];
+        };
+        let percent = |share: f32| share * 100.0;
+        vec![
+            format!(
+                "user {:.0}%  nice {:.0}%  system {:.0}%",
+                percent(b.user),
+                percent(b.nice),
+                percent(b.system)
+            ),
+            // iowait and steal are channels of their own
+            format!(
+                "irq {:.0}%  softirq {:.0}%",
+                percent(b.irq),
+                percent(b.softirq)
+            ),
+        ]
+    }
+
+    fn raw(&self) -> Vec<Row> {
+        self.rows.clone()
+    }
+
+    fn replay(&self) -> Option<SharedReplay> {
+        self.source.replay()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 
-            let last_usage = last.usage(prev);
-            let prev_usage = prev.usage(pprev);
-            Some(prev_usage + (last_usage - prev_usage) * delta)
-        }).collect()
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
     }
 }
 
 impl Default for CPUMetrics {
     fn default() -> Self {
-        let mut s = CPUMetrics {
-            samples: HashMap::new(),
-        };
-        s.sample();
-        s
+        let proc_root = PathBuf::from("/proc");
+        let source = Box::new(ProcStat::new(&proc_root));
+        CPUMetrics::new(proc_root, source, CpuSelection::default())
     }
 }
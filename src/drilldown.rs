@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use glyphon::{Buffer, Color, FontSystem, TextArea, TextBounds};
+
+use crate::cpu::{self, CPUMetrics};
+use crate::metrics::SysMetrics;
+use crate::overlay::{Overlay, PANEL};
+use crate::sparklines;
+use crate::text::{shape, size};
+
+const REFRESH: Duration = Duration::from_secs(1);
+const FONT_SIZE: f32 = 14.0;
+// Wider when the lines are
+const WIDTH: f32 = 420.0;
+const PADDING: f32 = 10.0;
+const GRAPH_HEIGHT: f32 = 100.0;
+const GRAPH_SECONDS: f32 = 120.0;
+const MAX_TASKS: usize = 12;
+const MAX_INTERRUPTS: usize = 6;
+// Clock ticks per second in /proc/*/stat when the kernel doesn't say, which is
+// what every common architecture uses
+const USER_HZ: f32 = 100.0;
+// The auxiliary vector entry with the clock ticks, see getauxval(3)
+const AT_CLKTCK: usize = 17;
+
+struct Task {
+    pid: String,
+    tid: String,
+    comm: String,
+    state: char,
+    // Share of the core over the last refresh
+    usage: f32,
+}
+
+struct Counter {
+    name: String,
+    total: u64,
+    per_second: f32,
+}
+
+struct IdleState {
+    name: String,
+    // Share of the last refresh spent in this state
+    residency: f32,
+}
+
+// Everything we know about one core, refreshed once a second. Rates are
+// computed against the previous refresh.
+#[derive(Default)]
+struct CoreDetails {
+    tasks: Vec<Task>,
+    interrupts: Vec<Counter>,
+    interrupt_rate: f32,
+    softirqs: Vec<Counter>,
+    idle_states: Vec<IdleState>,
+    frequency_mhz: Option<f32>,
+    // Clock ticks per second in /proc/*/stat
+    user_hz: f32,
+
+    last_task_times: HashMap<String, u64>,
+    last_interrupts: HashMap<String, u64>,
+    last_softirqs: HashMap<String, u64>,
+    last_idle_times: HashMap<String, u64>,
+}
+
+impl CoreDetails {
+    fn refresh(&mut self, proc_root: &Path, cpu_id: &str, cpu: usize, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f32().max(f32::EPSILON);
+        self.refresh_tasks(proc_root, cpu, seconds);
+        (self.interrupts, self.interrupt_rate) = counters(
+            &proc_root.join("interrupts"),
+            cpu,
+            seconds,
+            &mut self.last_interrupts,
+        );
+        self.interrupts.truncate(MAX_INTERRUPTS);
+        (self.softirqs, _) = counters(
+            &proc_root.join("softirqs"),
+            cpu,
+            seconds,
+            &mut self.last_softirqs,
+        );
+        self.refresh_idle_states(cpu_id, seconds);
+        self.frequency_mhz = cpu::frequency_mhz(cpu_id);
+    }
+
+    // Threads whose last run was on this core, busiest first
+    fn refresh_tasks(&mut self, proc_root: &Path, cpu: usize, seconds: f32) {
+        let mut times = HashMap::new();
+        let mut tasks = Vec::new();
+        for process in read_dir_names(proc_root) {
+            if !process.bytes().all(|b| b.is_ascii_digit()) {
+                continue;
+            }
+            let task_dir = proc_root.join(&process).join("task");
+            for tid in read_dir_names(&task_dir) {
+                let Ok(stat) = fs::read_to_string(task_dir.join(&tid).join("stat")) else {
+                    continue;
+                };
+                // The command name is in parentheses and may contain spaces
+                let (Some(open), Some(close)) = (stat.find('('), stat.rfind(')')) else {
+                    continue;
+                };
+                let comm = stat[open + 1..close].to_string();
+                // Fields from the state (3rd) onwards
+                let fields: Vec<&str> = stat[close + 1..].split_whitespace().collect();
+                let field = |n: usize| fields.get(n - 3).copied().unwrap_or_default();
+                if field(39).parse::<usize>().ok() != Some(cpu) {
+                    continue;
+                }
+                let time =
+                    field(14).parse::<u64>().unwrap_or(0) + field(15).parse::<u64>().unwrap_or(0);
+                let previous = self.last_task_times.get(&tid).copied().unwrap_or(time);
+                times.insert(tid.clone(), time);
+                tasks.push(Task {
+                    pid: process.clone(),
+                    tid,
+                    comm,
+                    state: field(3).chars().next().unwrap_or('?'),
+                    usage: time.saturating_sub(previous) as f32 / self.user_hz / seconds,
+                });
+            }
+        }
+        tasks.sort_by(|a, b| {
+            (b.state == 'R')
+                .cmp(&(a.state == 'R'))
+                .then(b.usage.total_cmp(&a.usage))
+        });
+        tasks.truncate(MAX_TASKS);
+        self.tasks = tasks;
+        self.last_task_times = times;
+    }
+
+    // sysfs has no root of its own like procfs, it is always this machine's
+    fn refresh_idle_states(&mut self, cpu_id: &str, seconds: f32) {
+        let dir = Path::new("/sys/devices/system/cpu")
+            .join(cpu_id)
+            .join("cpuidle");
+        let mut states = read_dir_names(&dir)
+            .into_iter()
+            .filter(|state| state.starts_with("state"))
+            .collect::<Vec<_>>();
+        states.sort();
+
+        self.idle_states.clear();
+        for state in states {
+            let read = |file: &str| fs::read_to_string(dir.join(&state).join(file)).ok();
+            let (Some(name), Some(time)) = (read("name"), read("time")) else {
+                continue;
+            };
+            // Residency is reported in microseconds
+            let time: u64 = time.trim().parse().unwrap_or(0);
+            let previous = self
+                .last_idle_times
+                .insert(state.clone(), time)
+                .unwrap_or(time);
+            self.idle_states.push(IdleState {
+                name: name.trim().to_string(),
+                residency: time.saturating_sub(previous) as f32 / 1e6 / seconds,
+            });
+        }
+    }
+
+    // Everything below the usage line, which changes every frame
+    fn text(&self) -> String {
+        let mut lines = vec![];
+        if let Some(mhz) = self.frequency_mhz {
+            lines.push(format!("frequency  {:.2} GHz", mhz / 1000.0));
+        }
+        if !self.idle_states.is_empty() {
+            let states = self
+                .idle_states
+                .iter()
+                .map(|state| format!("{} {:.0}%", state.name, state.residency * 100.0))
+                .collect::<Vec<_>>();
+            lines.push(format!("idle  {}", states.join("  ")));
+        }
+
+        lines.push(String::new());
+        lines.push("tasks".to_string());
+        for task in &self.tasks {
+            let id = if task.pid == task.tid {
+                task.pid.clone()
+            } else {
+                format!("{}/{}", task.pid, task.tid)
+            };
+            lines.push(format!(
+                "  {} {:>5.1}%  {}  {}",
+                task.state,
+                task.usage * 100.0,
+                id,
+                task.comm
+            ));
+        }
+
+        lines.push(String::new());
+        lines.push(format!("interrupts  {:.0}/s", self.interrupt_rate));
+        for interrupt in &self.interrupts {
+            lines.push(format!(
+                "  {:>8.0}/s  {:>12}  {}",
+                interrupt.per_second, interrupt.total, interrupt.name
+            ));
+        }
+
+        lines.push(String::new());
+        lines.push("softirqs".to_string());
+        for softirq in &self.softirqs {
+            lines.push(format!(
+                "  {:>8.0}/s  {:>12}  {}",
+                softirq.per_second, softirq.total, softirq.name
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+// The clock tick rate the kernel handed this process, as sysconf(_SC_CLK_TCK)
+// would return it
+fn user_hz() -> f32 {
+    let Ok(auxv) = fs::read("/proc/self/auxv") else {
+        return USER_HZ;
+    };
+    // Pairs of native words, a type and its value
+    let word = std::mem::size_of::<usize>();
+    auxv.chunks_exact(2 * word)
+        .find_map(|entry| {
+            let (key, value) = entry.split_at(word);
+            let key = usize::from_ne_bytes(key.try_into().ok()?);
+            let value = usize::from_ne_bytes(value.try_into().ok()?);
+            (key == AT_CLKTCK && value > 0).then_some(value as f32)
+        })
+        .unwrap_or(USER_HZ)
+}
+
+fn read_dir_names(dir: &Path) -> Vec<String> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Per-CPU counter tables like /proc/interrupts and /proc/softirqs: a header
+// with one column per CPU, then one row per source. Returns the rows for one
+// column, busiest first, along with the summed rate.
+fn counters(
+    path: &Path,
+    cpu: usize,
+    seconds: f32,
+    last: &mut HashMap<String, u64>,
+) -> (Vec<Counter>, f32) {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return (Vec::new(), 0.0);
+    };
+    let mut lines = contents.lines();
+    let Some(header) = lines.next() else {
+        return (Vec::new(), 0.0);
+    };
+    let column = header
+        .split_whitespace()
+        .position(|name| name == format!("CPU{}", cpu));
+    let Some(column) = column else {
+        return (Vec::new(), 0.0);
+    };
+
+    let mut rows = Vec::new();
+    let mut rate = 0.0;
+    for line in lines {
+        let Some((source, rest)) = line.split_once(':') else {
+            continue;
+        };
+        let words: Vec<&str> = rest.split_whitespace().collect();
+        let Some(total) = words
+            .get(column)
+            .and_then(|count| count.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        // Everything after the per-CPU columns describes the source
+        let description = words
+            .iter()
+            .skip_while(|word| word.parse::<u64>().is_ok())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let source = source.trim().to_string();
+        let name = if description.is_empty() {
+            source.clone()
+        } else {
+            format!("{} {}", source, description)
+        };
+        let previous = last.insert(source, total).unwrap_or(total);
+        let per_second = total.saturating_sub(previous) as f32 / seconds;
+        rate += per_second;
+        rows.push(Counter {
+            name,
+            total,
+            per_second,
+        });
+    }
+    rows.sort_by(|a, b| {
+        b.per_second
+            .total_cmp(&a.per_second)
+            .then(b.total.cmp(&a.total))
+    });
+    (rows, rate)
+}
+
+// Refreshes the details of one core on a thread of its own, walking every
+// task in /proc can take a while. The thread ends once the worker is dropped.
+struct Worker {
+    cpu: usize,
+    receiver: mpsc::Receiver<String>,
+}
+
+impl Worker {
+    fn start(proc_root: PathBuf, cpu_id: String, cpu: usize, number: usize) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut details = CoreDetails {
+                user_hz: user_hz(),
+                ..Default::default()
+            };
+            let mut elapsed = REFRESH;
+            loop {
+                let start = Instant::now();
+                details.refresh(&proc_root, &cpu_id, number, elapsed);
+                if sender.send(details.text()).is_err() {
+                    return;
+                }
+                thread::sleep(REFRESH.saturating_sub(start.elapsed()));
+                elapsed = start.elapsed();
+            }
+        });
+        Worker { cpu, receiver }
+    }
+}
+
+// Detail panel for the pinned core
+pub struct DrillDown {
+    worker: Option<Worker>,
+    // The newest text from the worker
+    details: String,
+    buffer: Option<Buffer>,
+    text: String,
+    visible: bool,
+    left: f32,
+    top: f32,
+}
+
+impl Default for DrillDown {
+    fn default() -> Self {
+        DrillDown {
+            worker: None,
+            details: String::new(),
+            buffer: None,
+            text: String::new(),
+            visible: false,
+            left: 0.0,
+            top: 0.0,
+        }
+    }
+}
+
+impl DrillDown {
+    pub fn update(
+        &mut self,
+        font_system: &mut FontSystem,
+        overlay: &mut Overlay,
+        sys_metrics: &SysMetrics,
+        top: f32,
+    ) {
+        // Only cores have anything to drill down into
+        let core = sys_metrics.selected.and_then(|index| {
+            let (source, instance) = sys_metrics.locate(index)?;
+            let cpu_metrics = source.as_any().downcast_ref::<CPUMetrics>()?;
+            Some((index, cpu_metrics, cpu_metrics.cpu_ids().nth(instance)?))
+        });
+        let Some((cpu, cpu_metrics, cpu_id)) = core else {
+            self.worker = None;
+            self.visible = false;
+            return;
+        };
+        // The kernel numbers cores by the digits in their /proc/stat name
+        let Some(number) = cpu_id.trim_start_matches("cpu").parse::<usize>().ok() else {
+            self.visible = false;
+            return;
+        };
+
+        // The tasks, interrupts and idle states would be this machine's, not
+        // those of a recorded or made up core
+        if !cpu_metrics.is_live() {
+            self.worker = None;
+            self.details = "details not available in playback".to_string();
+        } else if self.worker.as_ref().is_none_or(|worker| worker.cpu != cpu) {
+            let proc_root = cpu_metrics.proc_root().to_path_buf();
+            self.worker = Some(Worker::start(proc_root, cpu_id.to_string(), cpu, number));
+            self.details.clear();
+        }
+        if let Some(details) = self
+            .worker
+            .as_ref()
+            .and_then(|worker| worker.receiver.try_iter().last())
+        {
+            self.details = details;
+        }
+
+        let usage = sys_metrics.values.get(cpu).copied().unwrap_or(0.0);
+        let text = format!("{}  {:.0}%\n{}", cpu_id, usage * 100.0, self.details);
+        if text != self.text {
+            self.buffer = Some(shape(font_system, &text, FONT_SIZE));
+            self.text = text;
+        }
+        let (text_width, text_height) = self.buffer.as_ref().map_or((0.0, 0.0), size);
+        let width = (text_width + 2.0 * PADDING).max(WIDTH);
+
+        let left = PADDING;
+        overlay.rect(
+            left,
+            top,
+            width,
+            GRAPH_HEIGHT + text_height + 3.0 * PADDING,
+            PANEL,
+        );
+        sparklines::graph(
+            overlay,
+            sys_metrics.history(cpu),
+            (GRAPH_SECONDS * sys_metrics.sample_rate_hz).ceil() as usize,
+            left + PADDING,
+            top + PADDING,
+            width - 2.0 * PADDING,
+            GRAPH_HEIGHT,
+        );
+
+        self.left = left + PADDING;
+        self.top = top + GRAPH_HEIGHT + 2.0 * PADDING;
+        self.visible = true;
+    }
+
+    pub fn text_area(&self) -> Option<TextArea<'_>> {
+        if !self.visible {
+            return None;
+        }
+        Some(TextArea {
+            buffer: self.buffer.as_ref()?,
+            left: self.left,
+            top: self.top,
+            scale: 1.0,
+            bounds: TextBounds::default(),
+            default_color: Color::rgba(255, 255, 255, 255),
+            custom_glyphs: &[],
+        })
+    }
+}
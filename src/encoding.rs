@@ -0,0 +1,197 @@
+use anyhow::Context;
+
+use crate::config::{ChannelBinding, EncodingConfig};
+use crate::source::MetricSource;
+
+// How an instance is drawn, each between 0 and 1. The order is the one of
+// the vertex attributes in shader.wgsl.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Visuals {
+    pub height: f32,
+    pub color: f32,
+    pub width: f32,
+    pub glow: f32,
+}
+
+impl Visuals {
+    // Just the first channel, as for the history rows of the waterfall
+    pub fn of(value: f32) -> Self {
+        Visuals {
+            height: value,
+            color: value,
+            width: 1.0,
+            glow: 0.0,
+        }
+    }
+
+    // The per instance vertex data, see `SysMetrics::desc`
+    pub fn to_raw(self, highlight: f32) -> [f32; 5] {
+        [self.height, self.color, self.width, self.glow, highlight]
+    }
+}
+
+// Where one visual channel gets its value from
+enum Input {
+    // The first channel as the source interpolates it, 0 to 1 already
+    First,
+    Channel(Reading),
+    Constant(f32),
+}
+
+// Readings of a channel for the whole group, in the channel's unit
+struct Reading {
+    channel: usize,
+    min: f32,
+    // The highest reading in the group when unset
+    max: Option<f32>,
+    previous: Vec<f32>,
+    current: Vec<f32>,
+}
+
+impl Reading {
+    fn read(&mut self, source: &dyn MetricSource, size: usize) {
+        // Instances without the channel, like cores without a sensor, sit at
+        // the bottom of the range
+        let current = (0..size)
+            .map(|instance| source.value(instance, self.channel).unwrap_or(self.min))
+            .collect::<Vec<_>>();
+        self.previous = std::mem::replace(&mut self.current, current);
+        if self.previous.len() != size {
+            self.previous = self.current.clone();
+        }
+    }
+
+    // `delta` of the way from the previous reading to the current one
+    fn normalized(&self, delta: f32) -> Vec<f32> {
+        let max = self
+            .max
+            .unwrap_or_else(|| self.current.iter().copied().fold(self.min, f32::max));
+        let span = max - self.min;
+        self.previous
+            .iter()
+            .zip(&self.current)
+            .map(|(previous, current)| {
+                let value = previous + (current - previous) * delta;
+                if span > 0.0 {
+                    ((value - self.min) / span).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+}
+
+// Which metric channel of a source drives the height, color, width and glow
+// of its bars. Unless configured otherwise the first channel drives height and
+// color, and bars are full width without glow.
+pub struct Encoding {
+    size: usize,
+    height: Input,
+    color: Input,
+    width: Input,
+    glow: Input,
+}
+
+impl Encoding {
+    pub fn new(size: usize) -> Self {
+        Encoding {
+            size,
+            height: Input::First,
+            color: Input::First,
+            width: Input::Constant(1.0),
+            glow: Input::Constant(0.0),
+        }
+    }
+
+    pub fn configure(
+        source: &dyn MetricSource,
+        size: usize,
+        config: &EncodingConfig,
+    ) -> anyhow::Result<Self> {
+        let input = |visual: &str, binding: &Option<ChannelBinding>, default: Input| {
+            let Some(binding) = binding else {
+                return Ok(default);
+            };
+            Encoding::input(source, binding)
+                .with_context(|| format!("encoding.{}.{}", source.name(), visual))
+        };
+        let mut encoding = Encoding {
+            size,
+            height: input("height", &config.height, Input::First)?,
+            color: input("color", &config.color, Input::First)?,
+            width: input("width", &config.width, Input::Constant(1.0))?,
+            glow: input("glow", &config.glow, Input::Constant(0.0))?,
+        };
+        encoding.read(source);
+        Ok(encoding)
+    }
+
+    fn input(source: &dyn MetricSource, binding: &ChannelBinding) -> anyhow::Result<Input> {
+        let channels = source.channels();
+        let Some(channel) = channels
+            .iter()
+            .position(|channel| channel.name == binding.channel)
+        else {
+            let names = channels
+                .iter()
+                .map(|channel| channel.name)
+                .collect::<Vec<_>>();
+            anyhow::bail!(
+                "{} has no channel '{}', expected one of {}",
+                source.name(),
+                binding.channel,
+                names.join(", ")
+            );
+        };
+        if channel == 0 && binding.min.is_none() && binding.max.is_none() {
+            return Ok(Input::First);
+        }
+        let range = channels[channel].range;
+        Ok(Input::Channel(Reading {
+            channel,
+            min: binding.min.or(range.map(|(min, _)| min)).unwrap_or(0.0),
+            max: binding.max.or(range.map(|(_, max)| max)),
+            previous: Vec::new(),
+            current: Vec::new(),
+        }))
+    }
+
+    // Takes in a new sample of the source
+    pub fn read(&mut self, source: &dyn MetricSource) {
+        for input in [
+            &mut self.height,
+            &mut self.color,
+            &mut self.width,
+            &mut self.glow,
+        ] {
+            if let Input::Channel(reading) = input {
+                reading.read(source, self.size);
+            }
+        }
+    }
+
+    // `first` is the first channel of every instance as drawn, `delta` how far
+    // the other channels are from their previous reading to the current one
+    pub fn visuals(&self, first: &[f32], delta: f32) -> Vec<Visuals> {
+        let values = |input: &Input| match input {
+            Input::First => first.to_vec(),
+            Input::Channel(reading) => reading.normalized(delta.clamp(0.0, 1.0)),
+            Input::Constant(value) => vec![*value; self.size],
+        };
+        let (height, color, width, glow) = (
+            values(&self.height),
+            values(&self.color),
+            values(&self.width),
+            values(&self.glow),
+        );
+        (0..self.size)
+            .map(|i| Visuals {
+                height: height.get(i).copied().unwrap_or(0.0),
+                color: color.get(i).copied().unwrap_or(0.0),
+                width: width.get(i).copied().unwrap_or(1.0),
+                glow: glow.get(i).copied().unwrap_or(0.0),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,372 @@
+use std::time::{Duration, Instant};
+
+use cgmath::{InnerSpace, Vector2, Zero};
+use winit::event::{Touch, TouchPhase, WindowEvent};
+
+// Momentum dies down by this factor per second after the fingers lift
+const FRICTION: f32 = 4.0;
+// Weight of the newest movement in the velocity estimate
+const VELOCITY_SMOOTHING: f32 = 0.5;
+// Slower than this and the momentum stops, in pixels and degrees per second
+const MIN_VELOCITY: f32 = 1.0;
+// Fingers that rest this long before lifting don't fling
+const FLING_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Camera movement asked for by touch and trackpad gestures
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gesture {
+    // One finger drag in pixels, turns the camera around the target
+    pub orbit: Vector2<f32>,
+    // Factor for the camera distance, below 1 zooms in
+    pub zoom: f32,
+    // Two finger twist in degrees, clockwise on screen
+    pub rotate: f32,
+}
+
+impl Default for Gesture {
+    fn default() -> Self {
+        Gesture {
+            orbit: Vector2::zero(),
+            zoom: 1.0,
+            rotate: 0.0,
+        }
+    }
+}
+
+impl Gesture {
+    pub fn is_none(&self) -> bool {
+        self.orbit.is_zero() && self.zoom == 1.0 && self.rotate == 0.0
+    }
+}
+
+// Turns raw touch points and trackpad gestures into camera movement, and
+// keeps it going with some momentum once the fingers lift. Time comes in
+// from the caller so event sequences can be replayed.
+pub struct GestureTracker {
+    // Fingers currently down, in the order they touched
+    touches: Vec<(u64, Vector2<f32>)>,
+    // Between the start and end of a trackpad pinch or twist
+    trackpad_active: bool,
+    last_move: Option<Instant>,
+    // Per second: pixels of orbit, natural log of the zoom, degrees of twist
+    orbit_velocity: Vector2<f32>,
+    zoom_velocity: f32,
+    rotate_velocity: f32,
+}
+
+impl Default for GestureTracker {
+    fn default() -> Self {
+        GestureTracker {
+            touches: Vec::new(),
+            trackpad_active: false,
+            last_move: None,
+            orbit_velocity: Vector2::zero(),
+            zoom_velocity: 0.0,
+            rotate_velocity: 0.0,
+        }
+    }
+}
+
+impl GestureTracker {
+    // The movement caused by `event`, None when it isn't a gesture
+    pub fn event(&mut self, event: &WindowEvent, now: Instant) -> Option<Gesture> {
+        match event {
+            WindowEvent::Touch(touch) => Some(self.touch(touch, now)),
+            WindowEvent::PinchGesture { delta, phase, .. } => {
+                let delta = *delta as f32;
+                let gesture = Gesture {
+                    // Magnifying brings the camera closer
+                    zoom: if delta.is_finite() && delta > -1.0 {
+                        1.0 / (1.0 + delta)
+                    } else {
+                        1.0
+                    },
+                    ..Gesture::default()
+                };
+                Some(self.trackpad(gesture, *phase, now))
+            }
+            WindowEvent::RotationGesture { delta, phase, .. } => {
+                let gesture = Gesture {
+                    // Reported counter-clockwise
+                    rotate: if delta.is_finite() { -delta } else { 0.0 },
+                    ..Gesture::default()
+                };
+                Some(self.trackpad(gesture, *phase, now))
+            }
+            _ => None,
+        }
+    }
+
+    // What is left of the momentum after `dt`, nothing while fingers are down
+    pub fn momentum(&mut self, dt: Duration) -> Gesture {
+        if !self.touches.is_empty() || self.trackpad_active {
+            return Gesture::default();
+        }
+        let dt = dt.as_secs_f32();
+        let gesture = Gesture {
+            orbit: self.orbit_velocity * dt,
+            zoom: (self.zoom_velocity * dt).exp(),
+            rotate: self.rotate_velocity * dt,
+        };
+
+        let decay = (-FRICTION * dt).exp();
+        self.orbit_velocity *= decay;
+        self.zoom_velocity *= decay;
+        self.rotate_velocity *= decay;
+        if self.orbit_velocity.magnitude() < MIN_VELOCITY {
+            self.orbit_velocity = Vector2::zero();
+        }
+        // Zoom velocity is relative, a pixel doesn't mean anything there
+        if self.zoom_velocity.abs() < MIN_VELOCITY / 100.0 {
+            self.zoom_velocity = 0.0;
+        }
+        if self.rotate_velocity.abs() < MIN_VELOCITY {
+            self.rotate_velocity = 0.0;
+        }
+        gesture
+    }
+
+    // Drop any momentum, for when something else takes over the camera
+    pub fn stop(&mut self) {
+        self.orbit_velocity = Vector2::zero();
+        self.zoom_velocity = 0.0;
+        self.rotate_velocity = 0.0;
+    }
+
+    fn touch(&mut self, touch: &Touch, now: Instant) -> Gesture {
+        let location = Vector2::new(touch.location.x as f32, touch.location.y as f32);
+        let index = self.touches.iter().position(|(id, _)| *id == touch.id);
+        match (touch.phase, index) {
+            (TouchPhase::Started, _) => {
+                // A new finger changes the gesture, start measuring afresh
+                self.stop();
+                self.last_move = None;
+                match index {
+                    Some(i) => self.touches[i].1 = location,
+                    None => self.touches.push((touch.id, location)),
+                }
+                Gesture::default()
+            }
+            (TouchPhase::Moved, Some(i)) => {
+                let gesture = self.touch_moved(i, location);
+                self.touches[i].1 = location;
+                self.track(gesture, now);
+                gesture
+            }
+            (TouchPhase::Ended, Some(i)) => {
+                self.touches.remove(i);
+                // Lifting one of two fingers leaves the other orbiting, only
+                // the last finger keeps the momentum
+                if !self.touches.is_empty() || self.resting(now) {
+                    self.stop();
+                }
+                self.last_move = None;
+                Gesture::default()
+            }
+            (TouchPhase::Cancelled, Some(i)) => {
+                self.touches.remove(i);
+                self.stop();
+                self.last_move = None;
+                Gesture::default()
+            }
+            _ => Gesture::default(),
+        }
+    }
+
+    fn touch_moved(&self, i: usize, location: Vector2<f32>) -> Gesture {
+        let previous = self.touches[i].1;
+        match self.touches.len() {
+            1 => Gesture {
+                orbit: location - previous,
+                ..Gesture::default()
+            },
+            // Pinch and twist with the first two fingers, ignore any others
+            2.. if i < 2 => {
+                let other = self.touches[1 - i].1;
+                let before = previous - other;
+                let after = location - other;
+                if before.magnitude() < 1.0 || after.magnitude() < 1.0 {
+                    return Gesture::default();
+                }
+                // Screen y points down, so this is clockwise
+                let twist = after.y.atan2(after.x) - before.y.atan2(before.x);
+                Gesture {
+                    zoom: before.magnitude() / after.magnitude(),
+                    rotate: wrap_degrees(twist.to_degrees()),
+                    ..Gesture::default()
+                }
+            }
+            _ => Gesture::default(),
+        }
+    }
+
+    fn trackpad(&mut self, gesture: Gesture, phase: TouchPhase, now: Instant) -> Gesture {
+        self.trackpad_active = matches!(phase, TouchPhase::Started | TouchPhase::Moved);
+        match phase {
+            TouchPhase::Started => {
+                self.stop();
+                self.last_move = None;
+            }
+            TouchPhase::Moved => self.track(gesture, now),
+            TouchPhase::Ended => {
+                if self.resting(now) {
+                    self.stop();
+                }
+                self.last_move = None;
+            }
+            TouchPhase::Cancelled => {
+                self.stop();
+                self.last_move = None;
+            }
+        }
+        gesture
+    }
+
+    fn resting(&self, now: Instant) -> bool {
+        self.last_move
+            .is_none_or(|last_move| now.duration_since(last_move) > FLING_TIMEOUT)
+    }
+
+    // Update the velocity estimate used for the momentum
+    fn track(&mut self, gesture: Gesture, now: Instant) {
+        if let Some(last_move) = self.last_move {
+            let dt = now.duration_since(last_move).as_secs_f32();
+            if dt > 0.0 {
+                let blend = |velocity: f32, delta: f32| {
+                    velocity + (delta / dt - velocity) * VELOCITY_SMOOTHING
+                };
+                self.orbit_velocity = Vector2::new(
+                    blend(self.orbit_velocity.x, gesture.orbit.x),
+                    blend(self.orbit_velocity.y, gesture.orbit.y),
+                );
+                self.zoom_velocity = blend(self.zoom_velocity, gesture.zoom.ln());
+                self.rotate_velocity = blend(self.rotate_velocity, gesture.rotate);
+            }
+        }
+        self.last_move = Some(now);
+    }
+}
+
+fn wrap_degrees(degrees: f32) -> f32 {
+    (degrees + 540.0).rem_euclid(360.0) - 180.0
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::dpi::PhysicalPosition;
+    use winit::event::DeviceId;
+
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    fn touch(id: u64, phase: TouchPhase, x: f64, y: f64) -> WindowEvent {
+        WindowEvent::Touch(Touch {
+            device_id: DeviceId::dummy(),
+            phase,
+            location: PhysicalPosition::new(x, y),
+            force: None,
+            id,
+        })
+    }
+
+    // Drags one finger to the right a frame at a time and returns the time of
+    // the last move
+    fn drag(tracker: &mut GestureTracker, start: Instant, frames: u32) -> Instant {
+        tracker.event(&touch(0, TouchPhase::Started, 0.0, 0.0), start);
+        let mut now = start;
+        for frame in 1..=frames {
+            now = start + FRAME * frame;
+            tracker.event(&touch(0, TouchPhase::Moved, frame as f64 * 10.0, 0.0), now);
+        }
+        now
+    }
+
+    #[test]
+    fn one_finger_orbits() {
+        let mut tracker = GestureTracker::default();
+        let now = Instant::now();
+        tracker.event(&touch(0, TouchPhase::Started, 10.0, 10.0), now);
+        let gesture = tracker
+            .event(&touch(0, TouchPhase::Moved, 15.0, 7.0), now + FRAME)
+            .unwrap();
+        assert_eq!(gesture.orbit, Vector2::new(5.0, -3.0));
+        assert_eq!(gesture.zoom, 1.0);
+        assert_eq!(gesture.rotate, 0.0);
+    }
+
+    #[test]
+    fn pinch_zooms_by_the_distance_ratio() {
+        let mut tracker = GestureTracker::default();
+        let now = Instant::now();
+        tracker.event(&touch(0, TouchPhase::Started, 0.0, 0.0), now);
+        tracker.event(&touch(1, TouchPhase::Started, 100.0, 0.0), now);
+        let gesture = tracker
+            .event(&touch(1, TouchPhase::Moved, 200.0, 0.0), now + FRAME)
+            .unwrap();
+        assert!((gesture.zoom - 0.5).abs() < 1e-6);
+        assert_eq!(gesture.orbit, Vector2::zero());
+
+        let pinch = WindowEvent::PinchGesture {
+            device_id: DeviceId::dummy(),
+            delta: 0.25,
+            phase: TouchPhase::Moved,
+        };
+        let gesture = tracker.event(&pinch, now).unwrap();
+        assert!((gesture.zoom - 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn twist_is_clockwise_on_screen() {
+        let mut tracker = GestureTracker::default();
+        let now = Instant::now();
+        tracker.event(&touch(0, TouchPhase::Started, 0.0, 0.0), now);
+        tracker.event(&touch(1, TouchPhase::Started, 100.0, 0.0), now);
+        // Screen y points down, so moving the right finger down is clockwise
+        let gesture = tracker
+            .event(&touch(1, TouchPhase::Moved, 100.0, 100.0), now + FRAME)
+            .unwrap();
+        assert!((gesture.rotate - 45.0).abs() < 1e-3);
+
+        // Trackpads report counter-clockwise
+        let rotation = WindowEvent::RotationGesture {
+            device_id: DeviceId::dummy(),
+            delta: 10.0,
+            phase: TouchPhase::Moved,
+        };
+        assert_eq!(tracker.event(&rotation, now).unwrap().rotate, -10.0);
+    }
+
+    #[test]
+    fn fling_decays_to_nothing() {
+        let mut tracker = GestureTracker::default();
+        let last_move = drag(&mut tracker, Instant::now(), 10);
+        tracker.event(&touch(0, TouchPhase::Ended, 100.0, 0.0), last_move);
+
+        let mut previous = f32::MAX;
+        let mut frames = 0;
+        loop {
+            let gesture = tracker.momentum(FRAME);
+            if gesture.is_none() {
+                break;
+            }
+            let speed = gesture.orbit.magnitude();
+            assert!(gesture.orbit.x > 0.0);
+            assert!(speed < previous);
+            previous = speed;
+            frames += 1;
+            assert!(frames < 1000, "momentum never stopped");
+        }
+        assert!(frames > 1);
+        assert!(tracker.momentum(FRAME).is_none());
+    }
+
+    #[test]
+    fn resting_finger_does_not_fling() {
+        let mut tracker = GestureTracker::default();
+        let last_move = drag(&mut tracker, Instant::now(), 10);
+        let lift = last_move + FLING_TIMEOUT + FRAME;
+        tracker.event(&touch(0, TouchPhase::Ended, 100.0, 0.0), lift);
+        assert!(tracker.momentum(FRAME).is_none());
+    }
+}
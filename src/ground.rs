@@ -0,0 +1,284 @@
+use std::fmt;
+use std::str::FromStr;
+
+use wgpu::util::DeviceExt;
+
+use crate::camera::CameraController;
+use crate::metrics::MAX_BAR_HEIGHT;
+
+// Utilization levels that get a reference plane
+const LEVELS: [f32; 4] = [0.25, 0.5, 0.75, 1.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum GroundMode {
+    GridAndLevels,
+    Grid,
+    Hidden,
+}
+
+impl GroundMode {
+    pub const ALL: [GroundMode; 3] = [
+        GroundMode::GridAndLevels,
+        GroundMode::Grid,
+        GroundMode::Hidden,
+    ];
+
+    pub fn next(self) -> Self {
+        match self {
+            GroundMode::GridAndLevels => GroundMode::Grid,
+            GroundMode::Grid => GroundMode::Hidden,
+            GroundMode::Hidden => GroundMode::GridAndLevels,
+        }
+    }
+}
+
+impl fmt::Display for GroundMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GroundMode::GridAndLevels => "grid-and-levels",
+            GroundMode::Grid => "grid",
+            GroundMode::Hidden => "hidden",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for GroundMode {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        GroundMode::ALL
+            .into_iter()
+            .find(|mode| mode.to_string() == name)
+            .ok_or_else(|| anyhow::anyhow!("unknown ground mode '{}'", name))
+    }
+}
+
+impl TryFrom<String> for GroundMode {
+    type Error = anyhow::Error;
+
+    fn try_from(name: String) -> anyhow::Result<Self> {
+        name.parse()
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct GroundUniform {
+    origin: [f32; 2],
+    size: [f32; 2],
+    spacing: f32,
+    _padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PlaneRaw {
+    height: f32,
+    opacity: f32,
+    grid: f32,
+}
+
+impl PlaneRaw {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<PlaneRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<f32>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32,
+                },
+            ],
+        }
+    }
+}
+
+pub struct Ground {
+    pub mode: GroundMode,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    plane_buffer: wgpu::Buffer,
+    pipeline_layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl Ground {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Ground Uniform"),
+            size: std::mem::size_of::<GroundUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("ground_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("ground_bind_group"),
+        });
+
+        // The ground itself comes first, followed by one plane per level
+        let planes = std::iter::once(PlaneRaw {
+            height: 0.0,
+            opacity: 0.6,
+            grid: 1.0,
+        })
+        .chain(LEVELS.iter().map(|level| PlaneRaw {
+            height: level * MAX_BAR_HEIGHT,
+            opacity: 0.04,
+            grid: 0.0,
+        }))
+        .collect::<Vec<_>>();
+        let plane_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Ground Planes"),
+            contents: bytemuck::cast_slice(&planes),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Ground Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = Ground::pipeline(device, &pipeline_layout, format, sample_count);
+
+        Ground {
+            mode: GroundMode::GridAndLevels,
+            uniform_buffer,
+            bind_group,
+            plane_buffer,
+            pipeline_layout,
+            format,
+            render_pipeline,
+        }
+    }
+
+    fn pipeline(
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Ground Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("ground.wgsl").into()),
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            cache: None,
+            label: Some("Ground Pipeline"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[PlaneRaw::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                // Planes are seen from above as well as from below
+                cull_mode: None,
+                ..Default::default()
+            },
+            // Translucent, so test against the bars but don't occlude them
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    // The pipeline has to match the multisampling of the render pass
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.render_pipeline =
+            Ground::pipeline(device, &self.pipeline_layout, self.format, sample_count);
+    }
+
+    // Cover the given bounds with one cell of margin on each side so the grid
+    // lines fall between the instances
+    pub fn set_bounds(
+        &self,
+        queue: &wgpu::Queue,
+        min: cgmath::Point3<f32>,
+        max: cgmath::Point3<f32>,
+        spacing: f32,
+    ) {
+        let uniform = GroundUniform {
+            origin: [min.x - spacing, min.z - spacing],
+            size: [max.x - min.x + 2.0 * spacing, max.z - min.z + 2.0 * spacing],
+            spacing,
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn render(&self, render_pass: &mut wgpu::RenderPass, camera_controller: &CameraController) {
+        let planes = match self.mode {
+            GroundMode::GridAndLevels => 1 + LEVELS.len() as u32,
+            GroundMode::Grid => 1,
+            GroundMode::Hidden => return,
+        };
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &camera_controller.camera().bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.plane_buffer.slice(..));
+        render_pass.draw(0..6, 0..planes);
+    }
+}
@@ -0,0 +1,78 @@
+// Ground plane with a grid, plus translucent reference planes at fixed
+// utilization levels.
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct Ground {
+    origin: vec2<f32>,
+    size: vec2<f32>,
+    spacing: f32,
+}
+
+@group(1) @binding(0)
+var<uniform> ground: Ground;
+
+struct PlaneInput {
+    @location(0) height: f32,
+    @location(1) opacity: f32,
+    @location(2) grid: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) opacity: f32,
+    @location(2) grid: f32,
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    plane: PlaneInput,
+) -> VertexOutput {
+    // Two triangles covering the unit square
+    var corners = array<vec2<f32>, 6>(
+        vec2(0.0, 0.0),
+        vec2(0.0, 1.0),
+        vec2(1.0, 0.0),
+        vec2(1.0, 0.0),
+        vec2(0.0, 1.0),
+        vec2(1.0, 1.0),
+    );
+    let xz = ground.origin + corners[vertex_index] * ground.size;
+    let world_position = vec3<f32>(xz.x, plane.height, xz.y);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    out.opacity = plane.opacity;
+    out.grid = plane.grid;
+    return out;
+}
+
+// 1.0 on a line, 0.0 away from it, anti-aliased to about a pixel
+fn line_intensity(coord: vec2<f32>) -> f32 {
+    let distance = abs(fract(coord - 0.5) - 0.5) / fwidth(coord);
+    return 1.0 - min(min(distance.x, distance.y), 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let local = in.world_position.xz - ground.origin;
+
+    if in.grid > 0.5 {
+        let line = line_intensity(local / ground.spacing);
+        let color = mix(vec3<f32>(0.02, 0.02, 0.03), vec3<f32>(0.35, 0.35, 0.4), line);
+        return vec4<f32>(color, max(in.opacity, line));
+    }
+
+    // Reference planes are mostly see-through with a brighter outline so the
+    // level can be read from any angle
+    let edge = line_intensity(local / ground.size);
+    return vec4<f32>(vec3<f32>(0.8, 0.8, 1.0), mix(in.opacity, 0.6, edge));
+}
@@ -0,0 +1,113 @@
+use glyphon::{Buffer, Color, FontSystem, TextArea, TextBounds};
+
+use crate::bindings::{Action, Bindings};
+use crate::overlay::{Overlay, PANEL};
+use crate::text::{shape, size};
+
+const FONT_SIZE: f32 = 15.0;
+const PADDING: f32 = 16.0;
+const COLUMN_GAP: f32 = 24.0;
+
+// Every action and what it is bound to, in the middle of the window
+pub struct Help {
+    pub enabled: bool,
+    // Bindings on the left, what they do on the right
+    keys: Option<Buffer>,
+    descriptions: Option<Buffer>,
+    text: String,
+    left: f32,
+    top: f32,
+    keys_width: f32,
+}
+
+impl Default for Help {
+    fn default() -> Self {
+        Help {
+            enabled: false,
+            keys: None,
+            descriptions: None,
+            text: String::new(),
+            left: 0.0,
+            top: 0.0,
+            keys_width: 0.0,
+        }
+    }
+}
+
+impl Help {
+    pub fn update(
+        &mut self,
+        font_system: &mut FontSystem,
+        overlay: &mut Overlay,
+        bindings: &Bindings,
+        width: f32,
+        height: f32,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut keys = vec![];
+        let mut descriptions = vec![];
+        for action in Action::ALL {
+            let bound = bindings
+                .keys(action)
+                .map(|key| key.to_string())
+                .collect::<Vec<_>>();
+            keys.push(if bound.is_empty() {
+                "-".to_string()
+            } else {
+                bound.join(", ")
+            });
+            descriptions.push(action.description());
+        }
+        let (keys, descriptions) = (keys.join("\n"), descriptions.join("\n"));
+
+        // Only reshape when the bindings changed
+        let text = format!("{}\n{}", keys, descriptions);
+        if text != self.text {
+            self.keys = Some(shape(font_system, &keys, FONT_SIZE));
+            self.descriptions = Some(shape(font_system, &descriptions, FONT_SIZE));
+            self.text = text;
+        }
+        let (Some(keys), Some(descriptions)) = (&self.keys, &self.descriptions) else {
+            return;
+        };
+        let (keys_width, text_height) = size(keys);
+        let (descriptions_width, _) = size(descriptions);
+
+        let panel_width = keys_width + COLUMN_GAP + descriptions_width + 2.0 * PADDING;
+        let panel_height = text_height + 2.0 * PADDING;
+        let left = ((width - panel_width) / 2.0).max(0.0);
+        let top = ((height - panel_height) / 2.0).max(0.0);
+        overlay.rect(left, top, panel_width, panel_height, PANEL);
+
+        self.left = left + PADDING;
+        self.top = top + PADDING;
+        self.keys_width = keys_width;
+    }
+
+    pub fn text_areas(&self) -> impl Iterator<Item = TextArea<'_>> {
+        let keys = self.keys.as_ref().map(|buffer| TextArea {
+            buffer,
+            left: self.left,
+            top: self.top,
+            scale: 1.0,
+            bounds: TextBounds::default(),
+            default_color: Color::rgba(255, 200, 120, 255),
+            custom_glyphs: &[],
+        });
+        let descriptions = self.descriptions.as_ref().map(|buffer| TextArea {
+            buffer,
+            left: self.left + self.keys_width + COLUMN_GAP,
+            top: self.top,
+            scale: 1.0,
+            bounds: TextBounds::default(),
+            default_color: Color::rgba(255, 255, 255, 255),
+            custom_glyphs: &[],
+        });
+        keys.into_iter()
+            .chain(descriptions)
+            .filter(|_| self.enabled)
+    }
+}
@@ -0,0 +1,252 @@
+use cgmath::{InnerSpace, Point3, Vector2};
+use glyphon::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, TextArea, TextBounds};
+
+use crate::camera::{Camera, Projection};
+use crate::metrics::SysMetrics;
+use crate::ray::{Aabb, Ray};
+
+const FONT_SIZE: f32 = 14.0;
+// Labels are fully opaque up to NEAR and fade out towards FAR
+const NEAR: f32 = 10.0;
+const FAR: f32 = 60.0;
+// Side of the screen tiles bars are sorted into for the occlusion test
+const TILE: f32 = 64.0;
+
+struct Label {
+    buffer: Buffer,
+    text: String,
+    width: f32,
+}
+
+struct Placed {
+    label: usize,
+    left: f32,
+    top: f32,
+    alpha: f32,
+}
+
+// The bars covering each tile of the screen, so a label is only tested
+// against the bars drawn where it is
+struct Tiles {
+    columns: usize,
+    rows: usize,
+    tiles: Vec<Vec<usize>>,
+    // Bars reaching behind the camera, they could cover anything
+    everywhere: Vec<usize>,
+}
+
+impl Tiles {
+    fn new(camera: &Camera, bars: &[Aabb], width: f32, height: f32) -> Self {
+        let columns = (width / TILE).ceil().max(1.0) as usize;
+        let rows = (height / TILE).ceil().max(1.0) as usize;
+        let mut tiles = Tiles {
+            columns,
+            rows,
+            tiles: vec![Vec::new(); columns * rows],
+            everywhere: Vec::new(),
+        };
+        let view_projection = camera.build_view_projection_matrix();
+        for (i, bar) in bars.iter().enumerate() {
+            // Screen rectangle around the eight corners
+            let mut min = Vector2::new(f32::MAX, f32::MAX);
+            let mut max = Vector2::new(f32::MIN, f32::MIN);
+            let mut behind = false;
+            for corner in 0..8 {
+                let point = Point3::new(
+                    if corner & 1 == 0 {
+                        bar.min.x
+                    } else {
+                        bar.max.x
+                    },
+                    if corner & 2 == 0 {
+                        bar.min.y
+                    } else {
+                        bar.max.y
+                    },
+                    if corner & 4 == 0 {
+                        bar.min.z
+                    } else {
+                        bar.max.z
+                    },
+                );
+                let clip = view_projection * point.to_homogeneous();
+                if clip.w <= 0.0 {
+                    behind = true;
+                    break;
+                }
+                let pixel = Vector2::new(
+                    (clip.x / clip.w + 1.0) / 2.0 * width,
+                    (1.0 - clip.y / clip.w) / 2.0 * height,
+                );
+                min = Vector2::new(min.x.min(pixel.x), min.y.min(pixel.y));
+                max = Vector2::new(max.x.max(pixel.x), max.y.max(pixel.y));
+            }
+            if behind {
+                tiles.everywhere.push(i);
+                continue;
+            }
+            if max.x < 0.0 || max.y < 0.0 || min.x >= width || min.y >= height {
+                continue;
+            }
+            let (left, top) = (tiles.column(min.x), tiles.row(min.y));
+            let (right, bottom) = (tiles.column(max.x), tiles.row(max.y));
+            for row in top..=bottom {
+                for column in left..=right {
+                    tiles.tiles[row * columns + column].push(i);
+                }
+            }
+        }
+        tiles
+    }
+
+    fn column(&self, x: f32) -> usize {
+        ((x / TILE).max(0.0) as usize).min(self.columns - 1)
+    }
+
+    fn row(&self, y: f32) -> usize {
+        ((y / TILE).max(0.0) as usize).min(self.rows - 1)
+    }
+
+    // Bars that may cover the pixel
+    fn at(&self, pixel: Vector2<f32>) -> impl Iterator<Item = usize> + '_ {
+        let tile = &self.tiles[self.row(pixel.y) * self.columns + self.column(pixel.x)];
+        tile.iter().chain(&self.everywhere).copied()
+    }
+}
+
+// A `cpu7 83%` tag floating over every bar. Labels closer to the camera win
+// when they overlap, labels behind other bars are hidden.
+pub struct Labels {
+    pub enabled: bool,
+    labels: Vec<Label>,
+    placed: Vec<Placed>,
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Labels {
+            enabled: true,
+            labels: Vec::new(),
+            placed: Vec::new(),
+        }
+    }
+}
+
+impl Labels {
+    pub fn update(
+        &mut self,
+        font_system: &mut FontSystem,
+        camera: &Camera,
+        sys_metrics: &SysMetrics,
+        width: f32,
+        height: f32,
+    ) {
+        self.placed.clear();
+        if !self.enabled {
+            return;
+        }
+
+        let bars = sys_metrics.bar_bounds();
+        let eye = camera.position();
+        // The heatmap labels every tile at full opacity, centered on it
+        let flat = camera.projection == Projection::Orthographic;
+        let tiles = Tiles::new(camera, &bars, width, height);
+
+        let mut candidates = Vec::new();
+        for (i, (id, bar)) in sys_metrics.instance_ids().zip(&bars).enumerate() {
+            let value = sys_metrics.values.get(i).copied().unwrap_or(0.0);
+            self.set_text(font_system, i, format!("{} {:.0}%", id, value * 100.0));
+
+            let anchor = Point3::new(
+                (bar.min.x + bar.max.x) / 2.0,
+                bar.max.y + 0.3,
+                (bar.min.z + bar.max.z) / 2.0,
+            );
+            let Some(screen) = camera.project(anchor, width, height) else {
+                continue;
+            };
+            let distance = (anchor - eye).magnitude();
+            let alpha = if flat {
+                1.0
+            } else {
+                1.0 - ((distance - NEAR) / (FAR - NEAR)).clamp(0.0, 1.0)
+            };
+            if alpha < 0.05 {
+                continue;
+            }
+
+            let ray = Ray::between(eye, anchor);
+            let occluded = tiles
+                .at(screen)
+                .any(|j| j != i && ray.intersect(&bars[j]).is_some_and(|t| t < distance));
+            if occluded {
+                continue;
+            }
+            candidates.push((i, screen, distance, alpha));
+        }
+
+        // Nearest first, skip labels that would overlap one already placed
+        candidates.sort_by(|a, b| a.2.total_cmp(&b.2));
+        let mut taken: Vec<(Vector2<f32>, Vector2<f32>)> = Vec::new();
+        for (i, screen, _, alpha) in candidates {
+            let size = Vector2::new(self.labels[i].width, FONT_SIZE);
+            let above = if flat { size.y / 2.0 } else { size.y };
+            let min = Vector2::new(screen.x - size.x / 2.0, screen.y - above);
+            let max = min + size;
+            let overlaps = taken
+                .iter()
+                .any(|(a, b)| min.x < b.x && max.x > a.x && min.y < b.y && max.y > a.y);
+            if overlaps {
+                continue;
+            }
+            taken.push((min, max));
+            self.placed.push(Placed {
+                label: i,
+                left: min.x,
+                top: min.y,
+                alpha,
+            });
+        }
+    }
+
+    fn set_text(&mut self, font_system: &mut FontSystem, i: usize, text: String) {
+        while self.labels.len() <= i {
+            let mut buffer = Buffer::new(font_system, Metrics::new(FONT_SIZE, FONT_SIZE));
+            buffer.set_size(font_system, None, None);
+            self.labels.push(Label {
+                buffer,
+                text: String::new(),
+                width: 0.0,
+            });
+        }
+        let label = &mut self.labels[i];
+        if label.text == text {
+            return;
+        }
+        label.buffer.set_text(
+            font_system,
+            &text,
+            &Attrs::new().family(Family::Name("Inter")),
+            Shaping::Advanced,
+        );
+        label.buffer.shape_until_scroll(font_system, false);
+        label.width = label
+            .buffer
+            .layout_runs()
+            .map(|run| run.line_w)
+            .fold(0.0, f32::max);
+        label.text = text;
+    }
+
+    pub fn text_areas(&self) -> impl Iterator<Item = TextArea<'_>> {
+        self.placed.iter().map(|placed| TextArea {
+            buffer: &self.labels[placed.label].buffer,
+            left: placed.left,
+            top: placed.top,
+            scale: 1.0,
+            bounds: TextBounds::default(),
+            default_color: Color::rgba(255, 255, 255, (placed.alpha * 255.0) as u8),
+            custom_glyphs: &[],
+        })
+    }
+}
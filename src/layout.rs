@@ -0,0 +1,181 @@
+use std::f32::consts::{PI, TAU};
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use cgmath::Vector3;
+use serde::Deserialize;
+
+use crate::paths;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Layout {
+    Grid,
+    Ring,
+    Spiral,
+    Row,
+    Honeycomb,
+    // Explicit positions, in multiples of the bar spacing, one per CPU
+    Custom {
+        name: String,
+        positions: Vec<[f32; 2]>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayoutFile {
+    name: Option<String>,
+    positions: Vec<[f32; 2]>,
+}
+
+impl Layout {
+    pub fn builtin() -> Vec<Layout> {
+        vec![
+            Layout::Grid,
+            Layout::Ring,
+            Layout::Spiral,
+            Layout::Row,
+            Layout::Honeycomb,
+        ]
+    }
+
+    // The built-in layouts followed by the ones in the layouts config directory
+    pub fn available() -> Vec<Layout> {
+        let mut layouts = Layout::builtin();
+        if let Some(dir) = paths::config_dir().map(|dir| dir.join("layouts")) {
+            if let Ok(entries) = std::fs::read_dir(&dir) {
+                let mut files = entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+                    .collect::<Vec<_>>();
+                files.sort();
+                for file in files {
+                    match Layout::load(&file) {
+                        Ok(layout) => layouts.push(layout),
+                        Err(e) => log::error!("{:#}", e),
+                    }
+                }
+            }
+        }
+        layouts
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Layout> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read layout {}", path.display()))?;
+        let file: LayoutFile = toml::from_str(&contents)
+            .with_context(|| format!("invalid layout {}", path.display()))?;
+        if file.positions.is_empty() {
+            bail!("layout {} has no positions", path.display());
+        }
+        let name = file.name.unwrap_or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+        Ok(Layout::Custom {
+            name,
+            positions: file.positions,
+        })
+    }
+
+    // Positions of `n` instances on the floor, centered around the origin,
+    // with neighbours `spacing` apart
+    pub fn positions(&self, n: usize, spacing: f32) -> Vec<Vector3<f32>> {
+        let positions: Vec<Vector3<f32>> = match self {
+            Layout::Grid => {
+                let per_row = (n as f32).sqrt().ceil().max(1.0) as usize;
+                (0..n)
+                    .map(|i| {
+                        Vector3::new((i % per_row) as f32, 0.0, (i / per_row) as f32) * spacing
+                    })
+                    .collect()
+            }
+            Layout::Ring => {
+                // Neighbours one spacing apart along the circumference
+                let radius = (n as f32 * spacing / TAU).max(spacing);
+                (0..n)
+                    .map(|i| {
+                        let angle = TAU * i as f32 / n as f32;
+                        Vector3::new(angle.cos(), 0.0, angle.sin()) * radius
+                    })
+                    .collect()
+            }
+            Layout::Spiral => {
+                // Archimedean spiral with turns and neighbours one spacing apart
+                let growth = spacing / TAU;
+                let mut angle = TAU;
+                (0..n)
+                    .map(|_| {
+                        let radius = growth * angle;
+                        let position = Vector3::new(angle.cos(), 0.0, angle.sin()) * radius;
+                        angle += spacing / radius;
+                        position
+                    })
+                    .collect()
+            }
+            Layout::Row => (0..n)
+                .map(|i| Vector3::new(i as f32 * spacing, 0.0, 0.0))
+                .collect(),
+            Layout::Honeycomb => {
+                // Odd rows are shifted by half a cell so the rows nest
+                let per_row = (n as f32).sqrt().ceil().max(1.0) as usize;
+                let row_spacing = spacing * (PI / 3.0).sin();
+                (0..n)
+                    .map(|i| {
+                        let (column, row) = (i % per_row, i / per_row);
+                        let shift = if row % 2 == 1 { 0.5 } else { 0.0 };
+                        Vector3::new(
+                            (column as f32 + shift) * spacing,
+                            0.0,
+                            row as f32 * row_spacing,
+                        )
+                    })
+                    .collect()
+            }
+            Layout::Custom { positions, .. } => {
+                // CPUs missing from the file line up in a row in front of the others
+                let front = positions.iter().map(|p| p[1]).fold(f32::MIN, f32::max) + 2.0;
+                (0..n)
+                    .map(|i| match positions.get(i) {
+                        Some([x, z]) => Vector3::new(*x, 0.0, *z) * spacing,
+                        None => Vector3::new((i - positions.len()) as f32, 0.0, front) * spacing,
+                    })
+                    .collect()
+            }
+        };
+        center(positions)
+    }
+}
+
+fn center(mut positions: Vec<Vector3<f32>>) -> Vec<Vector3<f32>> {
+    if positions.is_empty() {
+        return positions;
+    }
+    let (mut min, mut max) = (positions[0], positions[0]);
+    for position in &positions {
+        min.x = min.x.min(position.x);
+        min.z = min.z.min(position.z);
+        max.x = max.x.max(position.x);
+        max.z = max.z.max(position.z);
+    }
+    let middle = (min + max) / 2.0;
+    for position in &mut positions {
+        *position -= Vector3::new(middle.x, 0.0, middle.z);
+    }
+    positions
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layout::Grid => write!(f, "grid"),
+            Layout::Ring => write!(f, "ring"),
+            Layout::Spiral => write!(f, "spiral"),
+            Layout::Row => write!(f, "row"),
+            Layout::Honeycomb => write!(f, "honeycomb"),
+            Layout::Custom { name, .. } => write!(f, "{}", name),
+        }
+    }
+}
@@ -1,10 +1,35 @@
+pub mod bindings;
 pub mod camera;
+pub mod cli;
+pub mod config;
 pub mod cpu;
+pub mod drilldown;
+pub mod encoding;
+pub mod gesture;
+pub mod ground;
+pub mod help;
+pub mod labels;
+pub mod layout;
 pub mod light;
+pub mod memory;
+pub mod menu;
+pub mod mesh;
 pub mod metrics;
 pub mod model;
+pub mod overlay;
+pub mod paths;
+pub mod ray;
+pub mod recording;
+pub mod scrubber;
+pub mod source;
+pub mod sparklines;
 pub mod state;
+pub mod synthetic;
 pub mod text;
+pub mod tooltip;
+pub mod tour;
+pub mod views;
+pub mod waterfall;
 pub mod window;
 
 #[macro_export]
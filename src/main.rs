@@ -1,5 +1,16 @@
+use lolitop::cli;
 use lolitop::window::run;
 
 fn main() {
-    pollster::block_on(run());
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        // Printed the help or the version
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("lolitop: {:#}", e);
+            eprintln!("Try 'lolitop --help' for more information.");
+            std::process::exit(2);
+        }
+    };
+    pollster::block_on(run(options));
 }
@@ -0,0 +1,207 @@
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::cpu::HISTORY_LEN;
+use crate::source::{Channel, CounterSource, MetricSource, Row, SharedReplay};
+
+const KB_PER_GIB: f32 = 1024.0 * 1024.0;
+
+// The row MemoryMetrics reads, MemTotal, MemAvailable, SwapTotal and SwapFree
+// in kB
+pub const MEMINFO: &str = "meminfo";
+
+const CHANNELS: [Channel; 3] = [
+    Channel {
+        name: "usage",
+        unit: "%",
+        range: Some((0.0, 100.0)),
+    },
+    Channel {
+        name: "used",
+        unit: "GiB",
+        range: None,
+    },
+    Channel {
+        name: "total",
+        unit: "GiB",
+        range: None,
+    },
+];
+
+// RAM or swap
+struct Pool {
+    id: &'static str,
+    // In kB, as /proc/meminfo has them
+    used: u64,
+    total: u64,
+    history: VecDeque<f32>,
+}
+
+impl Pool {
+    fn usage(&self) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.used as f32 / self.total as f32
+    }
+}
+
+// How full RAM and swap are, from the `meminfo` row of /proc/meminfo or of a
+// recording. Swap is always there, as 0 of 0 without any, as the instances
+// can't change once in the scene.
+pub struct MemoryMetrics {
+    source: Box<dyn CounterSource>,
+    // The newest `meminfo` row, for recording
+    row: Option<Vec<u64>>,
+    pools: Vec<Pool>,
+}
+
+impl MemoryMetrics {
+    pub fn new(source: Box<dyn CounterSource>) -> Self {
+        let pool = |id| Pool {
+            id,
+            used: 0,
+            total: 0,
+            history: VecDeque::new(),
+        };
+        let mut s = MemoryMetrics {
+            source,
+            row: None,
+            pools: vec![pool("mem"), pool("swap")],
+        };
+        s.sample();
+        s
+    }
+}
+
+// The live `meminfo` row from /proc/meminfo
+pub struct ProcMeminfo {
+    path: PathBuf,
+}
+
+impl ProcMeminfo {
+    pub fn new(proc_root: &Path) -> Self {
+        ProcMeminfo {
+            path: proc_root.join("meminfo"),
+        }
+    }
+}
+
+impl CounterSource for ProcMeminfo {
+    fn sample(&mut self) -> anyhow::Result<Vec<Row>> {
+        let meminfo = fs::read_to_string(&self.path)
+            .with_context(|| format!("failed to read {}", self.path.display()))?;
+        let fields: HashMap<&str, u64> = meminfo
+            .lines()
+            .filter_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                let kb = value.split_whitespace().next()?.parse().ok()?;
+                Some((name, kb))
+            })
+            .collect();
+        let counters = ["MemTotal", "MemAvailable", "SwapTotal", "SwapFree"]
+            .iter()
+            .map(|name| fields.get(name).copied().unwrap_or(0))
+            .collect();
+        Ok(vec![(MEMINFO.to_string(), counters)])
+    }
+
+    fn is_live(&self) -> bool {
+        true
+    }
+}
+
+impl MetricSource for MemoryMetrics {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn channels(&self) -> &[Channel] {
+        &CHANNELS
+    }
+
+    fn instances(&self) -> Vec<&str> {
+        self.pools.iter().map(|pool| pool.id).collect()
+    }
+
+    fn sample(&mut self) {
+        let rows = match self.source.sample() {
+            Ok(rows) => rows,
+            Err(e) => {
+                log::error!("{:#}", e);
+                return;
+            }
+        };
+        // A recording may only have it from some point on
+        let Some((_, row)) = rows.into_iter().find(|(id, _)| id == MEMINFO) else {
+            return;
+        };
+        let &[ram_total, available, swap_total, swap_free] = row.as_slice() else {
+            log::error!("{} has {} counters, expected 4", MEMINFO, row.len());
+            return;
+        };
+        self.row = Some(row);
+        // Available counts the caches the kernel would give back
+        let ram_used = ram_total.saturating_sub(available);
+        let swap_used = swap_total.saturating_sub(swap_free);
+
+        let readings = [(ram_used, ram_total), (swap_used, swap_total)];
+        for (pool, (used, total)) in self.pools.iter_mut().zip(readings) {
+            pool.used = used;
+            pool.total = total;
+            if pool.history.len() == HISTORY_LEN {
+                pool.history.pop_front();
+            }
+            pool.history.push_back(pool.usage());
+        }
+    }
+
+    fn values(&self, delta: f32) -> Vec<f32> {
+        self.pools
+            .iter()
+            .map(|pool| {
+                let mut newest = pool.history.iter().rev();
+                let last = newest.next().copied().unwrap_or(0.0);
+                let prev = newest.next().copied().unwrap_or(last);
+                prev + (last - prev) * delta
+            })
+            .collect()
+    }
+
+    fn history(&self, instance: usize) -> &VecDeque<f32> {
+        &self.pools[instance].history
+    }
+
+    fn value(&self, instance: usize, channel: usize) -> Option<f32> {
+        let pool = self.pools.get(instance)?;
+        match channel {
+            0 => Some(pool.usage() * 100.0),
+            1 => Some(pool.used as f32 / KB_PER_GIB),
+            2 => Some(pool.total as f32 / KB_PER_GIB),
+            _ => None,
+        }
+    }
+
+    fn raw(&self) -> Vec<Row> {
+        self.row
+            .iter()
+            .map(|row| (MEMINFO.to_string(), row.clone()))
+            .collect()
+    }
+
+    fn replay(&self) -> Option<SharedReplay> {
+        self.source.replay()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
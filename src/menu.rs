@@ -0,0 +1,284 @@
+use cgmath::Vector2;
+use glyphon::{Buffer, Color, FontSystem, TextArea, TextBounds};
+use winit::{
+    event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{Key, NamedKey},
+};
+
+use crate::overlay::{Overlay, PANEL};
+use crate::text::{shape, size};
+
+const FONT_SIZE: f32 = 16.0;
+const LINE_HEIGHT: f32 = FONT_SIZE * 1.3;
+const PADDING: f32 = 16.0;
+const COLUMN_GAP: f32 = 32.0;
+// Wide enough that the panel doesn't jump around while cycling values
+const VALUE_WIDTH: f32 = 160.0;
+
+// What the light setting cycles through
+pub const LIGHT_COLORS: [(&str, [f32; 3]); 4] = [
+    ("white", [1.0, 1.0, 1.0]),
+    ("warm", [1.0, 0.85, 0.6]),
+    ("cool", [0.7, 0.85, 1.0]),
+    ("dim", [0.5, 0.5, 0.5]),
+];
+// Degrees per second
+pub const LIGHT_ROTATIONS: [f32; 4] = [0.0, 15.0, 60.0, 180.0];
+// Rows of /proc/stat, as for --metrics
+pub const METRICS: [&str; 3] = ["cores", "total", "total,cores"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    Colormap,
+    Layout,
+    SampleRate,
+    Metrics,
+    LightColor,
+    LightRotation,
+    Msaa,
+    Ground,
+    Labels,
+    Sparklines,
+    Waterfall,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 11] = [
+        Setting::Colormap,
+        Setting::Layout,
+        Setting::SampleRate,
+        Setting::Metrics,
+        Setting::LightColor,
+        Setting::LightRotation,
+        Setting::Msaa,
+        Setting::Ground,
+        Setting::Labels,
+        Setting::Sparklines,
+        Setting::Waterfall,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Setting::Colormap => "Colormap",
+            Setting::Layout => "Layout",
+            Setting::SampleRate => "Sample rate",
+            Setting::Metrics => "Metrics",
+            Setting::LightColor => "Light",
+            Setting::LightRotation => "Light rotation",
+            Setting::Msaa => "Antialiasing",
+            Setting::Ground => "Ground",
+            Setting::Labels => "Labels",
+            Setting::Sparklines => "Sparklines",
+            Setting::Waterfall => "Waterfall",
+        }
+    }
+}
+
+// The option `step` places after `current`, wrapping around. Values that
+// aren't among the options step from the first one.
+pub fn step<T: PartialEq + Clone>(options: &[T], current: &T, step: isize) -> T {
+    let index = options
+        .iter()
+        .position(|option| option == current)
+        .unwrap_or(0);
+    let index = (index as isize + step).rem_euclid(options.len() as isize);
+    options[index as usize].clone()
+}
+
+pub enum MenuInput {
+    // Not for the menu, pass it on
+    Ignored,
+    Handled,
+    // Step the setting to its next (1) or previous (-1) value
+    Change(Setting, isize),
+}
+
+// Settings picked with the arrow keys or the mouse, drawn on the left
+pub struct Menu {
+    pub enabled: bool,
+    selected: usize,
+    cursor: Option<Vector2<f32>>,
+    labels: Option<Buffer>,
+    values: Option<Buffer>,
+    text: String,
+    left: f32,
+    top: f32,
+    width: f32,
+    names_width: f32,
+}
+
+impl Default for Menu {
+    fn default() -> Self {
+        Menu {
+            enabled: false,
+            selected: 0,
+            cursor: None,
+            labels: None,
+            values: None,
+            text: String::new(),
+            left: 0.0,
+            top: 0.0,
+            width: 0.0,
+            names_width: 0.0,
+        }
+    }
+}
+
+impl Menu {
+    // The setting row under the cursor, if any
+    fn row(&self) -> Option<usize> {
+        let cursor = self.cursor?;
+        // Rows start below the title
+        let y = cursor.y - self.top - PADDING - LINE_HEIGHT;
+        let x = cursor.x - self.left;
+        if x < 0.0 || x > self.width || y < 0.0 {
+            return None;
+        }
+        let row = (y / LINE_HEIGHT) as usize;
+        (row < Setting::ALL.len()).then_some(row)
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> MenuInput {
+        let count = Setting::ALL.len();
+        let selected = Setting::ALL[self.selected];
+        match event {
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+                match event.logical_key {
+                    Key::Named(NamedKey::ArrowUp) => {
+                        self.selected = (self.selected + count - 1) % count;
+                        MenuInput::Handled
+                    }
+                    Key::Named(NamedKey::ArrowDown) => {
+                        self.selected = (self.selected + 1) % count;
+                        MenuInput::Handled
+                    }
+                    Key::Named(NamedKey::ArrowLeft) => MenuInput::Change(selected, -1),
+                    Key::Named(NamedKey::ArrowRight | NamedKey::Enter) => {
+                        MenuInput::Change(selected, 1)
+                    }
+                    _ => MenuInput::Ignored,
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = Some(Vector2::new(position.x as f32, position.y as f32));
+                if let Some(row) = self.row() {
+                    self.selected = row;
+                }
+                MenuInput::Ignored
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+                MenuInput::Ignored
+            }
+            // Left click steps forward, right click back
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button,
+                ..
+            } => match (self.row(), button) {
+                (Some(row), MouseButton::Left) => MenuInput::Change(Setting::ALL[row], 1),
+                (Some(row), MouseButton::Right) => MenuInput::Change(Setting::ALL[row], -1),
+                (Some(_), _) => MenuInput::Handled,
+                (None, _) => MenuInput::Ignored,
+            },
+            WindowEvent::MouseWheel { delta, .. } => match self.row() {
+                Some(row) => {
+                    let y = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => *y,
+                        MouseScrollDelta::PixelDelta(position) => position.y as f32,
+                    };
+                    if y == 0.0 {
+                        MenuInput::Handled
+                    } else {
+                        MenuInput::Change(Setting::ALL[row], if y > 0.0 { 1 } else { -1 })
+                    }
+                }
+                None => MenuInput::Ignored,
+            },
+            _ => MenuInput::Ignored,
+        }
+    }
+
+    // `values` has the current value of each setting in Setting::ALL order,
+    // `hint` says how to close the menu
+    pub fn update(
+        &mut self,
+        font_system: &mut FontSystem,
+        overlay: &mut Overlay,
+        values: &[String],
+        hint: &str,
+        top: f32,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut labels = vec!["Settings".to_string()];
+        labels.extend(Setting::ALL.map(|setting| setting.label().to_string()));
+        labels.push(String::new());
+        labels.push(hint.to_string());
+        let (labels, values) = (labels.join("\n"), values.join("\n"));
+
+        // Only reshape when a value changed
+        let text = format!("{}\n{}", labels, values);
+        if text != self.text {
+            self.labels = Some(shape(font_system, &labels, FONT_SIZE));
+            self.values = Some(shape(font_system, &values, FONT_SIZE));
+            self.text = text;
+        }
+        let Some(labels) = &self.labels else {
+            return;
+        };
+        let (labels_width, text_height) = size(labels);
+        // The values line up after the longest setting name, the title and
+        // the hint may stick out further
+        let names_width = labels
+            .layout_runs()
+            .filter(|run| (1..=Setting::ALL.len()).contains(&run.line_i))
+            .map(|run| run.line_w)
+            .fold(0.0, f32::max);
+        let width = labels_width.max(names_width + COLUMN_GAP + VALUE_WIDTH) + 2.0 * PADDING;
+
+        self.left = PADDING;
+        self.top = top;
+        self.width = width;
+        self.names_width = names_width;
+        overlay.rect(
+            self.left,
+            self.top,
+            width,
+            text_height + 2.0 * PADDING,
+            PANEL,
+        );
+        overlay.rect(
+            self.left,
+            self.top + PADDING + LINE_HEIGHT * (self.selected + 1) as f32,
+            width,
+            LINE_HEIGHT,
+            [1.0, 0.6, 0.2, 0.25],
+        );
+    }
+
+    pub fn text_areas(&self) -> impl Iterator<Item = TextArea<'_>> {
+        let labels = self.labels.as_ref().map(|buffer| TextArea {
+            buffer,
+            left: self.left + PADDING,
+            top: self.top + PADDING,
+            scale: 1.0,
+            bounds: TextBounds::default(),
+            default_color: Color::rgba(255, 255, 255, 255),
+            custom_glyphs: &[],
+        });
+        // Next to the setting names, below the title
+        let values = self.values.as_ref().map(|buffer| TextArea {
+            buffer,
+            left: self.left + PADDING + self.names_width + COLUMN_GAP,
+            top: self.top + PADDING + LINE_HEIGHT,
+            scale: 1.0,
+            bounds: TextBounds::default(),
+            default_color: Color::rgba(255, 200, 120, 255),
+            custom_glyphs: &[],
+        });
+        labels.into_iter().chain(values).filter(|_| self.enabled)
+    }
+}
@@ -0,0 +1,491 @@
+use std::f32::consts::{PI, TAU};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, ensure, Context};
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, SquareMatrix, Vector3};
+
+use crate::model::{self, create_vertex, Model, Vertex};
+use crate::paths;
+
+// All shapes fill the same -1..1 box as `model::cube`, the shader stretches
+// that box into a bar standing on the floor.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Cube,
+    BevelledBox,
+    Cylinder,
+    HexPrism,
+    Sphere,
+    File(PathBuf),
+}
+
+impl Shape {
+    pub fn builtin() -> Vec<Shape> {
+        vec![
+            Shape::Cube,
+            Shape::BevelledBox,
+            Shape::Cylinder,
+            Shape::HexPrism,
+            Shape::Sphere,
+        ]
+    }
+
+    // The built-in shapes followed by any OBJ or glTF files the user dropped
+    // into the meshes data directory
+    pub fn available() -> Vec<Shape> {
+        let mut shapes = Shape::builtin();
+        if let Some(dir) = paths::data_dir().map(|dir| dir.join("meshes")) {
+            if let Ok(entries) = std::fs::read_dir(&dir) {
+                let mut files = entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| is_mesh_file(path))
+                    .collect::<Vec<_>>();
+                files.sort();
+                shapes.extend(files.into_iter().map(Shape::File));
+            }
+        }
+        shapes
+    }
+
+    pub fn model(&self) -> anyhow::Result<Model> {
+        Ok(match self {
+            Shape::Cube => model::cube(),
+            Shape::BevelledBox => bevelled_box(0.2),
+            Shape::Cylinder => prism(32, true),
+            Shape::HexPrism => prism(6, false),
+            Shape::Sphere => sphere(16, 32),
+            Shape::File(path) => load(path)?,
+        })
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Shape::Cube => write!(f, "cube"),
+            Shape::BevelledBox => write!(f, "bevelled box"),
+            Shape::Cylinder => write!(f, "cylinder"),
+            Shape::HexPrism => write!(f, "hexagonal prism"),
+            Shape::Sphere => write!(f, "sphere"),
+            Shape::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+fn is_mesh_file(path: &Path) -> bool {
+    matches!(
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .as_deref(),
+        Some("obj" | "gltf" | "glb")
+    )
+}
+
+// Collects vertices and triangles, flipping triangles so they wind counter
+// clockwise when seen from the side their normals point to
+#[derive(Default)]
+struct MeshBuilder {
+    vertices: Vec<Vertex>,
+    positions: Vec<Vector3<f32>>,
+    normals: Vec<Vector3<f32>>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn vertex(&mut self, position: Vector3<f32>, normal: Vector3<f32>) -> u32 {
+        let normal = normal.normalize();
+        self.vertices
+            .push(create_vertex(position.into(), normal.into()));
+        self.positions.push(position);
+        self.normals.push(normal);
+        (self.vertices.len() - 1) as u32
+    }
+
+    fn triangle(&mut self, a: u32, b: u32, c: u32) {
+        let [pa, pb, pc] = [a, b, c].map(|i| self.positions[i as usize]);
+        let face = (pb - pa).cross(pc - pa);
+        if face.magnitude2() < f32::EPSILON {
+            return;
+        }
+        let outward =
+            self.normals[a as usize] + self.normals[b as usize] + self.normals[c as usize];
+        if face.dot(outward) >= 0.0 {
+            self.indices.extend([a, b, c]);
+        } else {
+            self.indices.extend([a, c, b]);
+        }
+    }
+
+    fn quad(&mut self, corners: [Vector3<f32>; 4], normal: Vector3<f32>) {
+        let [a, b, c, d] = corners.map(|corner| self.vertex(corner, normal));
+        self.triangle(a, b, c);
+        self.triangle(a, c, d);
+    }
+
+    fn build(self) -> Model {
+        Model {
+            vertices: self.vertices,
+            vertex_indices: self.indices,
+        }
+    }
+}
+
+// A box with its edges and corners chamfered by `bevel`
+fn bevelled_box(bevel: f32) -> Model {
+    let mut mesh = MeshBuilder::default();
+    let inner = 1.0 - bevel;
+    let axes = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+    let signs = [-1.0, 1.0];
+
+    // Faces, inset from the edges
+    for (a, axis) in axes.iter().enumerate() {
+        let u = axes[(a + 1) % 3];
+        let v = axes[(a + 2) % 3];
+        for sign in signs {
+            let center = axis * sign;
+            mesh.quad(
+                [
+                    center + (-u - v) * inner,
+                    center + (u - v) * inner,
+                    center + (u + v) * inner,
+                    center + (-u + v) * inner,
+                ],
+                axis * sign,
+            );
+        }
+    }
+
+    // Edges, between each pair of neighbouring faces
+    for (a, axis) in axes.iter().enumerate() {
+        let u = axes[(a + 1) % 3];
+        let v = axes[(a + 2) % 3];
+        for su in signs {
+            for sv in signs {
+                mesh.quad(
+                    [
+                        u * su + v * sv * inner - axis * inner,
+                        u * su + v * sv * inner + axis * inner,
+                        u * su * inner + v * sv + axis * inner,
+                        u * su * inner + v * sv - axis * inner,
+                    ],
+                    u * su + v * sv,
+                );
+            }
+        }
+    }
+
+    // Corners
+    for sx in signs {
+        for sy in signs {
+            for sz in signs {
+                let normal = Vector3::new(sx, sy, sz);
+                let a = mesh.vertex(Vector3::new(sx, sy * inner, sz * inner), normal);
+                let b = mesh.vertex(Vector3::new(sx * inner, sy, sz * inner), normal);
+                let c = mesh.vertex(Vector3::new(sx * inner, sy * inner, sz), normal);
+                mesh.triangle(a, b, c);
+            }
+        }
+    }
+
+    mesh.build()
+}
+
+// A vertical prism with `segments` sides, smooth shaded sides make a cylinder
+fn prism(segments: u32, smooth: bool) -> Model {
+    let mut mesh = MeshBuilder::default();
+    let point = |angle: f32| Vector3::new(angle.cos(), 0.0, angle.sin());
+    let up = Vector3::unit_y();
+
+    for i in 0..segments {
+        let a0 = TAU * i as f32 / segments as f32;
+        let a1 = TAU * (i + 1) as f32 / segments as f32;
+        let (p0, p1) = (point(a0), point(a1));
+        let (n0, n1) = if smooth {
+            (p0, p1)
+        } else {
+            let n = point((a0 + a1) / 2.0);
+            (n, n)
+        };
+
+        // Side
+        let b0 = mesh.vertex(p0 - up, n0);
+        let b1 = mesh.vertex(p1 - up, n1);
+        let t0 = mesh.vertex(p0 + up, n0);
+        let t1 = mesh.vertex(p1 + up, n1);
+        mesh.triangle(b0, b1, t1);
+        mesh.triangle(b0, t1, t0);
+
+        // Caps
+        for y in [-1.0, 1.0] {
+            let center = mesh.vertex(up * y, up * y);
+            let c0 = mesh.vertex(p0 + up * y, up * y);
+            let c1 = mesh.vertex(p1 + up * y, up * y);
+            mesh.triangle(center, c0, c1);
+        }
+    }
+
+    mesh.build()
+}
+
+fn sphere(stacks: u32, slices: u32) -> Model {
+    let mut mesh = MeshBuilder::default();
+
+    let mut rings = Vec::new();
+    for stack in 0..=stacks {
+        let polar = PI * stack as f32 / stacks as f32;
+        let ring = (0..=slices)
+            .map(|slice| {
+                let azimuth = TAU * slice as f32 / slices as f32;
+                let position = Vector3::new(
+                    polar.sin() * azimuth.cos(),
+                    polar.cos(),
+                    polar.sin() * azimuth.sin(),
+                );
+                mesh.vertex(position, position)
+            })
+            .collect::<Vec<_>>();
+        rings.push(ring);
+    }
+
+    for stack in 0..stacks as usize {
+        for slice in 0..slices as usize {
+            let (a, b) = (rings[stack][slice], rings[stack][slice + 1]);
+            let (c, d) = (rings[stack + 1][slice], rings[stack + 1][slice + 1]);
+            mesh.triangle(a, b, d);
+            mesh.triangle(a, d, c);
+        }
+    }
+
+    mesh.build()
+}
+
+pub fn load(path: &Path) -> anyhow::Result<Model> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    let (positions, normals, indices) = match extension.as_deref() {
+        Some("obj") => load_obj(path),
+        Some("gltf" | "glb") => load_gltf(path),
+        _ => Err(anyhow!(
+            "unsupported mesh format, expected .obj, .gltf or .glb"
+        )),
+    }
+    .with_context(|| format!("failed to load mesh {}", path.display()))?;
+
+    if positions.is_empty() || indices.is_empty() {
+        bail!("mesh {} has no triangles", path.display());
+    }
+    // The indices come straight from the file
+    ensure!(
+        indices.len() % 3 == 0,
+        "mesh {} has {} indices, which is not a whole number of triangles",
+        path.display(),
+        indices.len()
+    );
+    if let Some(index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+        bail!(
+            "mesh {} has index {} past its {} vertices",
+            path.display(),
+            index,
+            positions.len()
+        );
+    }
+    // Normals that don't go with the vertices are made anew
+    let normals = normals.filter(|normals| normals.len() == positions.len());
+    Ok(fit(positions, normals, indices))
+}
+
+type Triangles = (Vec<Vector3<f32>>, Option<Vec<Vector3<f32>>>, Vec<u32>);
+
+fn load_obj(path: &Path) -> anyhow::Result<Triangles> {
+    let (models, _materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)?;
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut indices = Vec::new();
+    let mut has_normals = true;
+    for model in models {
+        let mesh = model.mesh;
+        let offset = positions.len() as u32;
+        positions.extend(
+            mesh.positions
+                .chunks_exact(3)
+                .map(|p| Vector3::new(p[0], p[1], p[2])),
+        );
+        has_normals &= mesh.normals.len() == mesh.positions.len();
+        normals.extend(
+            mesh.normals
+                .chunks_exact(3)
+                .map(|n| Vector3::new(n[0], n[1], n[2])),
+        );
+        indices.extend(mesh.indices.iter().map(|i| i.saturating_add(offset)));
+    }
+
+    Ok((positions, has_normals.then_some(normals), indices))
+}
+
+// Meshes are placed where the nodes of the scene put them, files without a
+// scene get their meshes as they are
+fn load_gltf(path: &Path) -> anyhow::Result<Triangles> {
+    let (document, buffers, _images) = gltf::import(path)?;
+
+    let mut triangles = (Vec::new(), Some(Vec::new()), Vec::new());
+    match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => {
+            for node in scene.nodes() {
+                add_gltf_node(&node, Matrix4::identity(), &buffers, &mut triangles);
+            }
+        }
+        None => {
+            for mesh in document.meshes() {
+                add_gltf_mesh(&mesh, Matrix4::identity(), &buffers, &mut triangles);
+            }
+        }
+    }
+    Ok(triangles)
+}
+
+fn add_gltf_node(
+    node: &gltf::Node,
+    parent: Matrix4<f32>,
+    buffers: &[gltf::buffer::Data],
+    triangles: &mut Triangles,
+) {
+    let transform = parent * Matrix4::from(node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        add_gltf_mesh(&mesh, transform, buffers, triangles);
+    }
+    for child in node.children() {
+        add_gltf_node(&child, transform, buffers, triangles);
+    }
+}
+
+fn add_gltf_mesh(
+    mesh: &gltf::Mesh,
+    transform: Matrix4<f32>,
+    buffers: &[gltf::buffer::Data],
+    (positions, normals, indices): &mut Triangles,
+) {
+    let linear = Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    );
+    // Normals go through the inverse transpose, mirroring flips the winding
+    let normal_matrix = linear
+        .invert()
+        .map_or(linear, |inverse| inverse.transpose());
+    let mirrored = linear.determinant() < 0.0;
+    for primitive in mesh.primitives() {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            continue;
+        }
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let Some(primitive_positions) = reader.read_positions() else {
+            continue;
+        };
+        let offset = positions.len() as u32;
+        positions.extend(
+            primitive_positions
+                .map(|position| (transform * Vector3::from(position).extend(1.0)).truncate()),
+        );
+        match reader.read_normals() {
+            Some(primitive_normals) => {
+                if let Some(normals) = normals {
+                    normals.extend(
+                        primitive_normals
+                            .map(|normal| (normal_matrix * Vector3::from(normal)).normalize()),
+                    );
+                }
+            }
+            None => *normals = None,
+        }
+        let start = indices.len();
+        match reader.read_indices() {
+            Some(primitive_indices) => indices.extend(
+                primitive_indices
+                    .into_u32()
+                    .map(|i| i.saturating_add(offset)),
+            ),
+            None => indices.extend(offset..positions.len() as u32),
+        }
+        if mirrored {
+            for triangle in indices[start..].chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+    }
+}
+
+// Area weighted vertex normals for meshes that don't bring their own
+fn smooth_normals(positions: &[Vector3<f32>], indices: &[u32]) -> Vec<Vector3<f32>> {
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+        let face = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        normals[a] += face;
+        normals[b] += face;
+        normals[c] += face;
+    }
+    normals
+}
+
+// Center the mesh and stretch it into the -1..1 box, keeping the horizontal
+// aspect ratio. Normals get the inverse of the same scaling.
+fn fit(
+    positions: Vec<Vector3<f32>>,
+    normals: Option<Vec<Vector3<f32>>>,
+    indices: Vec<u32>,
+) -> Model {
+    let normals = normals.unwrap_or_else(|| smooth_normals(&positions, &indices));
+
+    let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+    for position in &positions {
+        min = Vector3::new(
+            min.x.min(position.x),
+            min.y.min(position.y),
+            min.z.min(position.z),
+        );
+        max = Vector3::new(
+            max.x.max(position.x),
+            max.y.max(position.y),
+            max.z.max(position.z),
+        );
+    }
+    let center = (min + max) / 2.0;
+    let horizontal = ((max.x - min.x).max(max.z - min.z) / 2.0).max(f32::EPSILON);
+    let vertical = ((max.y - min.y) / 2.0).max(f32::EPSILON);
+    let scale = Vector3::new(horizontal, vertical, horizontal);
+
+    let vertices = positions
+        .iter()
+        .zip(normals.iter())
+        .map(|(position, normal)| {
+            let position = position - center;
+            let position = Vector3::new(
+                position.x / scale.x,
+                position.y / scale.y,
+                position.z / scale.z,
+            );
+            let normal = Vector3::new(normal.x * scale.x, normal.y * scale.y, normal.z * scale.z);
+            let normal = if normal.magnitude2() > 0.0 {
+                normal.normalize()
+            } else {
+                Vector3::unit_y()
+            };
+            create_vertex(position.into(), normal.into())
+        })
+        .collect();
+
+    Model {
+        vertices,
+        vertex_indices: indices,
+    }
+}
@@ -1,18 +1,152 @@
 use core::f32;
-use std::time::{Duration, Instant};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
 
-use cgmath::Rotation3;
+use cgmath::{EuclideanSpace, InnerSpace, Rotation3};
 use wgpu::util::DeviceExt;
 use wgpu::BufferDescriptor;
 
-use crate::cpu::CPUMetrics;
+use crate::config::EncodingConfig;
+use crate::encoding::{Encoding, Visuals};
+use crate::layout::Layout;
+use crate::ray::Aabb;
+use crate::recording::Recorder;
+use crate::source::MetricSource;
+use crate::waterfall::Waterfall;
 
+// Default distance between neighbouring bars, which are 2 wide
+pub const SPACING: f32 = 2.0;
+// Height of a bar at 100% utilization, bars grow up from the floor at y = 0
+pub const MAX_BAR_HEIGHT: f32 = 2.0;
+// Thickness of the tiles in the flat heatmap
+const FLAT_HEIGHT: f32 = 0.02;
+// Width of a bar at the bottom of its width channel, as in shader.wgsl
+const MIN_WIDTH: f32 = 0.25;
+
+// How usage maps to bar color, the index matches the switch in shader.wgsl
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum Colormap {
+    Fire,
+    Rainbow,
+    Cosmic,
+    White,
+}
+
+impl Colormap {
+    pub const ALL: [Colormap; 4] = [
+        Colormap::Fire,
+        Colormap::Rainbow,
+        Colormap::Cosmic,
+        Colormap::White,
+    ];
+}
+
+impl fmt::Display for Colormap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Colormap::Fire => "fire",
+            Colormap::Rainbow => "rainbow",
+            Colormap::Cosmic => "cosmic",
+            Colormap::White => "white",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Colormap {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        Colormap::ALL
+            .into_iter()
+            .find(|colormap| colormap.to_string() == name)
+            .ok_or_else(|| anyhow::anyhow!("unknown colormap '{}'", name))
+    }
+}
+
+impl TryFrom<String> for Colormap {
+    type Error = anyhow::Error;
+
+    fn try_from(name: String) -> anyhow::Result<Self> {
+        name.parse()
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BarUniform {
+    max_height: f32,
+    min_height: f32,
+    // 1.0 draws unlit tiles of equal height for the heatmap
+    flat: f32,
+    colormap: u32,
+}
+
+// Space between the instance groups of neighbouring sources, in spacings
+const GROUP_GAP: f32 = 2.0;
+
+// The usage history as it was when the display was paused. Sampling goes on
+// in the background, the snapshot keeps what we look at from scrolling away.
+struct Paused {
+    history: Vec<VecDeque<f32>>,
+    // Samples back from the newest one in `history`
+    back: usize,
+    // `history` up to and including the sample shown
+    shown: Vec<VecDeque<f32>>,
+}
+
+impl Paused {
+    fn seek(&mut self, back: usize) {
+        let len = self.history.iter().map(VecDeque::len).max().unwrap_or(0);
+        self.back = back.min(len.saturating_sub(1));
+        self.shown = self
+            .history
+            .iter()
+            .map(|history| {
+                let end = history.len().saturating_sub(self.back);
+                history.range(..end).copied().collect()
+            })
+            .collect();
+    }
+}
+
+// Everything drawn as bars. Each source gets a group of instances of its
+// own, one after the other. Indexes into `instances`, `values` and the
+// histories count through all groups.
 pub struct SysMetrics {
     last_sample_time: Instant,
-    cpu_usage_buffer: wgpu::Buffer,
-    pub cpu_metrics: CPUMetrics,
-    pub cpu_core_instances: Vec<Instance>,
+    value_buffer: wgpu::Buffer,
+    sources: Vec<Box<dyn MetricSource>>,
+    // Instances per source, as when the buffers were made
+    group_sizes: Vec<usize>,
+    pub instances: Vec<Instance>,
+    // Interpolated first channel per instance, as currently drawn
+    pub values: Vec<f32>,
+    // What the bars show of their channels, one encoding per source
+    encodings: Vec<Encoding>,
+    pub visuals: Vec<Visuals>,
+    // Instance under the cursor and the one pinned by clicking, drawn brighter
+    pub hovered: Option<usize>,
+    pub selected: Option<usize>,
     pub sample_rate_hz: f32,
+    // Keeps idle bars visible as a thin slab on the floor
+    pub min_bar_height: f32,
+    // Flatten the bars into a 2D heatmap colored by usage
+    pub flat: bool,
+    pub colormap: Colormap,
+    // Distance between neighbours in the layout
+    pub spacing: f32,
+    pub waterfall: Waterfall,
+    paused: Option<Paused>,
+    // Every sample is also written here while recording
+    pub recorder: Option<Recorder>,
+
+    bar_buffer: wgpu::Buffer,
+    pub bar_bind_group_layout: wgpu::BindGroupLayout,
+    bar_bind_group: wgpu::BindGroup,
 
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
@@ -23,111 +157,544 @@ pub struct SysMetrics {
 use crate::model;
 
 impl SysMetrics {
-    pub fn new(device: &wgpu::Device) -> Self {
-        let cpu_metrics = CPUMetrics::default();
-
+    pub fn new(device: &wgpu::Device, sources: Vec<Box<dyn MetricSource>>) -> Self {
         let last_sample_time = Instant::now();
 
-        let cpu_usage_buffer = device.create_buffer(&BufferDescriptor {
-            label: Some("CPU usage"),
+        let group_sizes = sources
+            .iter()
+            .map(|source| source.instances().len())
+            .collect::<Vec<_>>();
+        let count = group_sizes.iter().sum::<usize>();
+        let value_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Instance values"),
             usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            size: cpu_metrics.ncpus() as u64 * 4,
+            size: (count * std::mem::size_of::<[f32; 5]>()) as u64,
             mapped_at_creation: false,
         });
 
-        let model = model::cube();
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(&model.vertices),
-            usage: wgpu::BufferUsages::VERTEX,
+        let bar_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Bar Uniform"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            size: std::mem::size_of::<BarUniform>() as u64,
+            mapped_at_creation: false,
         });
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(&model.vertex_indices),
-            usage: wgpu::BufferUsages::INDEX,
+        let bar_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("bar_bind_group_layout"),
+            });
+        let bar_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bar_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: bar_buffer.as_entire_binding(),
+            }],
+            label: Some("bar_bind_group"),
         });
 
-        let num_indices = model.vertex_indices.len() as u32;
-
-        let ncpus = cpu_metrics.ncpus();
-        let cpu_core_instances = SysMetrics::instances(ncpus as u64);
+        let (vertex_buffer, index_buffer, num_indices) =
+            SysMetrics::mesh_buffers(device, &model::cube());
 
-        let instance_data = cpu_core_instances
-            .iter()
-            .map(Instance::to_raw)
+        let instances = SysMetrics::positions(&Layout::Grid, &group_sizes, SPACING)
+            .into_iter()
+            .map(|position| Instance {
+                position,
+                target: position,
+                rotation: cgmath::Quaternion::from_axis_angle(
+                    cgmath::Vector3::unit_z(),
+                    cgmath::Deg(0.0),
+                ),
+            })
             .collect::<Vec<_>>();
+
+        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Instance Buffer"),
             contents: bytemuck::cast_slice(&instance_data),
-            usage: wgpu::BufferUsages::VERTEX,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         });
+        let encodings = group_sizes
+            .iter()
+            .map(|size| Encoding::new(*size))
+            .collect();
         SysMetrics {
             last_sample_time,
-            cpu_metrics,
-            cpu_usage_buffer,
-            cpu_core_instances: cpu_core_instances,
+            value_buffer,
+            sources,
+            group_sizes,
+            instances,
+            values: Vec::new(),
+            encodings,
+            visuals: Vec::new(),
+            hovered: None,
+            selected: None,
             sample_rate_hz: 2.0,
+            min_bar_height: 0.05,
+            flat: false,
+            colormap: Colormap::Fire,
+            spacing: SPACING,
+            waterfall: Waterfall::new(device, count),
+            paused: None,
+            recorder: None,
+            bar_buffer,
+            bar_bind_group_layout,
+            bar_bind_group,
             vertex_buffer,
             index_buffer,
             num_indices,
             instance_buffer,
         }
     }
+    fn mesh_buffers(
+        device: &wgpu::Device,
+        model: &model::Model,
+    ) -> (wgpu::Buffer, wgpu::Buffer, u32) {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(&model.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(&model.vertex_indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        (
+            vertex_buffer,
+            index_buffer,
+            model.vertex_indices.len() as u32,
+        )
+    }
+
+    // Swap the mesh drawn for every instance
+    pub fn set_model(&mut self, device: &wgpu::Device, model: &model::Model) {
+        (self.vertex_buffer, self.index_buffer, self.num_indices) =
+            SysMetrics::mesh_buffers(device, model);
+    }
+
+    // Per instance height, color, width, glow and highlight, see Visuals
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
         wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<f32>() as wgpu::BufferAddress,
+            array_stride: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &[wgpu::VertexAttribute {
-                offset: 0,
-                shader_location: 9,
-                format: wgpu::VertexFormat::Float32,
-            }],
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<f32>() as wgpu::BufferAddress,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 11,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 13,
+                    format: wgpu::VertexFormat::Float32,
+                },
+            ],
+        }
+    }
+
+    // Binds channels to how the bars of each group look. Groups that fail to
+    // bind keep the built-in encoding, the first error is returned, as is one
+    // for groups none of the sources has.
+    pub fn set_encodings(
+        &mut self,
+        configs: &BTreeMap<String, EncodingConfig>,
+    ) -> anyhow::Result<()> {
+        let groups: Vec<&str> = self.sources.iter().map(|source| source.name()).collect();
+        let mut result = match configs
+            .keys()
+            .find(|group| !groups.contains(&group.as_str()))
+        {
+            Some(group) => Err(anyhow::anyhow!(
+                "unknown group encoding.{}, expected one of {}",
+                group,
+                groups.join(", ")
+            )),
+            None => Ok(()),
+        };
+        for ((source, size), encoding) in self
+            .sources
+            .iter()
+            .zip(&self.group_sizes)
+            .zip(&mut self.encodings)
+        {
+            *encoding = match configs.get(source.name()) {
+                Some(config) => match Encoding::configure(source.as_ref(), *size, config) {
+                    Ok(configured) => configured,
+                    Err(e) => {
+                        if result.is_ok() {
+                            result = Err(e);
+                        }
+                        Encoding::new(*size)
+                    }
+                },
+                None => Encoding::new(*size),
+            };
         }
+        result
+    }
+
+    pub fn sources(&self) -> &[Box<dyn MetricSource>] {
+        &self.sources
+    }
+
+    // Hands the sources over, to build a new SysMetrics from them
+    pub fn take_sources(&mut self) -> Vec<Box<dyn MetricSource>> {
+        std::mem::take(&mut self.sources)
+    }
+
+    // The first source of type T, for what only it has
+    pub fn source<T: 'static>(&self) -> Option<&T> {
+        self.sources
+            .iter()
+            .find_map(|source| source.as_any().downcast_ref())
+    }
+
+    pub fn source_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.sources
+            .iter_mut()
+            .find_map(|source| source.as_any_mut().downcast_mut())
+    }
+
+    // The source an instance belongs to and its index within that source
+    pub fn locate(&self, index: usize) -> Option<(&dyn MetricSource, usize)> {
+        let mut start = 0;
+        for (source, size) in self.sources.iter().zip(&self.group_sizes) {
+            if index < start + size {
+                return Some((source.as_ref(), index - start));
+            }
+            start += size;
+        }
+        None
+    }
+
+    // Names of all instances, like `cpu3` or `mem`
+    pub fn instance_ids(&self) -> impl Iterator<Item = &str> {
+        self.sources
+            .iter()
+            .zip(&self.group_sizes)
+            .flat_map(|(source, size)| source.instances().into_iter().take(*size))
+    }
+
+    pub fn highlights(&self) -> Vec<f32> {
+        (0..self.instances.len())
+            .map(|i| {
+                if self.selected == Some(i) {
+                    1.0
+                } else if self.hovered == Some(i) {
+                    0.5
+                } else {
+                    0.0
+                }
+            })
+            .collect()
     }
 
-    pub fn update(&mut self, queue: &wgpu::Queue) {
+    pub fn update(&mut self, dt: Duration, queue: &wgpu::Queue) {
+        self.animate_instances(dt, queue);
+
         let sample_period_s = 1.0 / self.sample_rate_hz;
         let now = Instant::now();
         if now - self.last_sample_time > Duration::from_secs_f32(sample_period_s) {
-            self.cpu_metrics.sample();
+            for (source, encoding) in self.sources.iter_mut().zip(&mut self.encodings) {
+                source.sample();
+                encoding.read(source.as_ref());
+            }
             self.last_sample_time = now;
+            self.record();
         }
+        let delta = now.duration_since(self.last_sample_time).as_secs_f32() / sample_period_s;
+        self.values = match &self.paused {
+            Some(paused) => paused
+                .shown
+                .iter()
+                .map(|history| history.back().copied().unwrap_or(0.0))
+                .collect(),
+            None => self
+                .sources
+                .iter()
+                .zip(&self.group_sizes)
+                .flat_map(|(source, size)| {
+                    // Instances that came or went since stay out of the scene
+                    let mut values = source.values(delta);
+                    values.resize(*size, 0.0);
+                    values
+                })
+                .collect(),
+        };
+        // Other channels have no history, paused bars show their newest reading
+        let delta = if self.paused.is_some() { 1.0 } else { delta };
+        let mut start = 0;
+        self.visuals = Vec::with_capacity(self.values.len());
+        for (encoding, size) in self.encodings.iter().zip(&self.group_sizes) {
+            self.visuals
+                .extend(encoding.visuals(&self.values[start..start + size], delta));
+            start += size;
+        }
+        let highlights = self.highlights();
+        let values = self
+            .visuals
+            .iter()
+            .zip(&highlights)
+            .map(|(visuals, highlight)| visuals.to_raw(*highlight))
+            .collect::<Vec<_>>();
+        queue.write_buffer(&self.value_buffer, 0, bytemuck::cast_slice(&values));
+        let histories = (0..self.instances.len())
+            .map(|index| self.history(index))
+            .collect::<Vec<_>>();
+        // Paused rows stand still
+        let scroll = if self.paused.is_some() {
+            0.0
+        } else {
+            delta.min(1.0)
+        };
+        self.waterfall.update(
+            queue,
+            &histories,
+            &self.instances,
+            &highlights,
+            self.spacing,
+            scroll,
+        );
+        let (max_height, min_height) = self.height_range();
         queue.write_buffer(
-            &self.cpu_usage_buffer,
+            &self.bar_buffer,
             0,
-            bytemuck::cast_slice(&self.cpu_metrics.interpolate_usage(
-                now.duration_since(self.last_sample_time).as_secs_f32() / sample_period_s,
-            )),
+            bytemuck::cast_slice(&[BarUniform {
+                max_height,
+                min_height,
+                flat: if self.flat { 1.0 } else { 0.0 },
+                colormap: self.colormap as u32,
+            }]),
         );
     }
 
-    fn instances(n_cpus: u64) -> Vec<Instance> {
-        static SPACING: f32 = 2.0;
+    // The recording is of the raw rows of every source, as they come from
+    // their counter sources
+    fn record(&mut self) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+        let rows: Vec<_> = self
+            .sources
+            .iter()
+            .flat_map(|source| source.raw())
+            .collect();
+        let rows = rows
+            .iter()
+            .map(|(row, counters)| (row.as_str(), counters.as_slice()));
+        if let Err(e) = recorder.record(SystemTime::now(), rows) {
+            log::error!("{:#}", e);
+            log::error!("stopped recording");
+            self.recorder = None;
+        }
+    }
 
-        let per_row = (n_cpus as f32).sqrt().ceil() as u64;
-        let displacement: cgmath::Vector3<f32> =
-            cgmath::Vector3::new((per_row - 1) as f32, 0., (per_row - 1) as f32) * SPACING / 2.;
-        (0..n_cpus)
-            .map(|i| {
-                let x = i % per_row;
-                let z = i / per_row;
-                let position = cgmath::Vector3 {
-                    x: SPACING * x as f32,
-                    y: 0.0,
-                    z: SPACING * z as f32,
-                } - displacement;
-
-                let rotation = cgmath::Quaternion::from_axis_angle(
-                    cgmath::Vector3::unit_z(),
-                    cgmath::Deg(0.0),
-                );
-                Instance { position, rotation }
+    // The first channel per sample period of one instance, oldest first,
+    // ending at the sample shown while paused
+    pub fn history(&self, index: usize) -> &VecDeque<f32> {
+        static EMPTY: VecDeque<f32> = VecDeque::new();
+        if let Some(history) = self
+            .paused
+            .as_ref()
+            .and_then(|paused| paused.shown.get(index))
+        {
+            return history;
+        }
+        match self.locate(index) {
+            Some((source, instance)) => source.history(instance),
+            None => &EMPTY,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    // Freezes the display on the newest sample
+    pub fn pause(&mut self) {
+        let history = (0..self.instances.len())
+            .map(|index| self.history(index).clone())
+            .collect();
+        let mut paused = Paused {
+            history,
+            back: 0,
+            shown: Vec::new(),
+        };
+        paused.seek(0);
+        self.paused = Some(paused);
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = None;
+    }
+
+    // Steps through the history, back for negative `samples`. Stepping back
+    // pauses first when live, there is nothing ahead of live.
+    pub fn scrub(&mut self, samples: isize) {
+        if self.paused.is_none() {
+            if samples >= 0 {
+                return;
+            }
+            self.pause();
+        }
+        let paused = self.paused.as_mut().unwrap();
+        paused.seek(paused.back.saturating_add_signed(-samples));
+    }
+
+    // Shows the sample `back` samples before the newest one of the snapshot
+    pub fn seek(&mut self, back: usize) {
+        if self.paused.is_none() {
+            self.pause();
+        }
+        self.paused.as_mut().unwrap().seek(back);
+    }
+
+    // Samples back from the newest and samples in the snapshot while paused
+    pub fn playhead(&self) -> Option<(usize, usize)> {
+        let paused = self.paused.as_ref()?;
+        let len = paused.history.iter().map(VecDeque::len).max().unwrap_or(0);
+        Some((paused.back, len))
+    }
+
+    // Axis aligned bounding box of all instances, from the floor up to a full bar
+    pub fn bounds(&self) -> (cgmath::Point3<f32>, cgmath::Point3<f32>) {
+        let mut min = cgmath::Point3::new(f32::MAX, 0.0, f32::MAX);
+        let mut max = cgmath::Point3::new(f32::MIN, MAX_BAR_HEIGHT, f32::MIN);
+        for instance in &self.instances {
+            min.x = min.x.min(instance.position.x - 1.0);
+            min.z = min
+                .z
+                .min(instance.position.z - 1.0 - self.waterfall.extent(self.spacing));
+            max.x = max.x.max(instance.position.x + 1.0);
+            max.z = max.z.max(instance.position.z + 1.0);
+        }
+        (min, max)
+    }
+
+    // Height at full usage and the minimum height, as passed to the shader
+    fn height_range(&self) -> (f32, f32) {
+        if self.flat {
+            (0.0, FLAT_HEIGHT)
+        } else {
+            (MAX_BAR_HEIGHT, self.min_bar_height)
+        }
+    }
+
+    pub fn bar_height(&self, value: f32) -> f32 {
+        let (max_height, min_height) = self.height_range();
+        (value * max_height).max(min_height)
+    }
+
+    // Box around each bar as currently drawn, in instance order
+    pub fn bar_bounds(&self) -> Vec<Aabb> {
+        self.instances
+            .iter()
+            .enumerate()
+            .map(|(i, instance)| {
+                let visuals = self.visuals.get(i).copied().unwrap_or(Visuals::of(0.0));
+                let half_width = MIN_WIDTH + (1.0 - MIN_WIDTH) * visuals.width;
+                let position = cgmath::Point3::from_vec(instance.position);
+                Aabb {
+                    min: position + cgmath::Vector3::new(-half_width, 0.0, -half_width),
+                    max: position
+                        + cgmath::Vector3::new(
+                            half_width,
+                            self.bar_height(visuals.height),
+                            half_width,
+                        ),
+                }
             })
-            .take(n_cpus as usize)
             .collect()
     }
 
+    // The first group is laid out around the origin, the others follow it
+    // to the right. Custom layouts list CPUs, the other groups use a grid.
+    fn positions(
+        layout: &Layout,
+        group_sizes: &[usize],
+        spacing: f32,
+    ) -> Vec<cgmath::Vector3<f32>> {
+        let mut positions: Vec<cgmath::Vector3<f32>> = Vec::new();
+        for (group, &size) in group_sizes.iter().enumerate() {
+            let layout = match layout {
+                Layout::Custom { .. } if group > 0 => &Layout::Grid,
+                layout => layout,
+            };
+            let mut group_positions = layout.positions(size, spacing);
+            if let Some(right) = positions.iter().map(|p| p.x).reduce(f32::max) {
+                let left = group_positions.iter().map(|p| p.x).fold(f32::MAX, f32::min);
+                let shift = right - left + GROUP_GAP * spacing;
+                for position in &mut group_positions {
+                    position.x += shift;
+                }
+            }
+            positions.extend(group_positions);
+        }
+        positions
+    }
+
+    // Instances glide over to their new positions in `update`
+    pub fn set_layout(&mut self, layout: &Layout) {
+        let positions = SysMetrics::positions(layout, &self.group_sizes, self.spacing);
+        for (instance, position) in self.instances.iter_mut().zip(positions) {
+            instance.target = position;
+        }
+    }
+
+    fn animate_instances(&mut self, dt: Duration, queue: &wgpu::Queue) {
+        static SPEED: f32 = 6.0;
+
+        let mut moving = false;
+        let t = 1.0 - (-SPEED * dt.as_secs_f32()).exp();
+        for instance in &mut self.instances {
+            let remaining = instance.target - instance.position;
+            if remaining.magnitude2() > 1e-6 {
+                instance.position += remaining * t;
+                moving = true;
+            } else if remaining.magnitude2() > 0.0 {
+                instance.position = instance.target;
+                moving = true;
+            }
+        }
+        if moving {
+            let instance_data = self
+                .instances
+                .iter()
+                .map(Instance::to_raw)
+                .collect::<Vec<_>>();
+            queue.write_buffer(
+                &self.instance_buffer,
+                0,
+                bytemuck::cast_slice(&instance_data),
+            );
+        }
+    }
+
     pub fn render(
         &self,
         render_pass: &mut wgpu::RenderPass,
@@ -138,26 +705,29 @@ impl SysMetrics {
         render_pass.set_pipeline(&render_pipeline);
         render_pass.set_bind_group(0, &camera_controller.camera().bind_group, &[]);
         render_pass.set_bind_group(1, light_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.bar_bind_group, &[]);
 
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
         render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
 
-        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.set_vertex_buffer(2, self.cpu_usage_buffer.slice(..));
-        render_pass.draw_indexed(
-            0..self.num_indices,
-            0,
-            0..self.cpu_core_instances.len() as _,
-        );
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.set_vertex_buffer(2, self.value_buffer.slice(..));
+        render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as _);
+        self.waterfall.render(render_pass, self.num_indices);
     }
 }
 
 pub struct Instance {
     position: cgmath::Vector3<f32>,
+    target: cgmath::Vector3<f32>,
     rotation: cgmath::Quaternion<f32>,
 }
 
 impl Instance {
+    pub fn position(&self) -> cgmath::Vector3<f32> {
+        self.position
+    }
+
     pub fn to_raw(&self) -> InstanceRaw {
         InstanceRaw {
             model: (cgmath::Matrix4::from_translation(self.position)
@@ -173,6 +743,14 @@ pub struct InstanceRaw {
     model: [[f32; 4]; 4],
 }
 
+impl From<cgmath::Matrix4<f32>> for InstanceRaw {
+    fn from(model: cgmath::Matrix4<f32>) -> Self {
+        InstanceRaw {
+            model: model.into(),
+        }
+    }
+}
+
 impl InstanceRaw {
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
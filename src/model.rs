@@ -5,7 +5,7 @@ pub struct Vertex {
     normal: [f32; 3],
 }
 
-fn create_vertex(position: [f32; 3], normal: [f32; 3]) -> Vertex {
+pub fn create_vertex(position: [f32; 3], normal: [f32; 3]) -> Vertex {
     Vertex { position, normal }
 }
 
@@ -24,7 +24,7 @@ impl Vertex {
                 wgpu::VertexAttribute {
                     offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
                     shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2,
+                    format: wgpu::VertexFormat::Float32x3,
                 },
             ],
         }
@@ -33,7 +33,7 @@ impl Vertex {
 
 pub struct Model {
     pub vertices: Vec<Vertex>,
-    pub vertex_indices: Vec<u16>,
+    pub vertex_indices: Vec<u32>,
 }
 
 pub fn cube() -> Model {
@@ -0,0 +1,43 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Ray {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn between(from: Point3<f32>, to: Point3<f32>) -> Self {
+        Ray::new(from, to - from)
+    }
+
+    // Distance along the ray to where it enters the box, using the slab method
+    pub fn intersect(&self, aabb: &Aabb) -> Option<f32> {
+        let mut near = f32::MIN;
+        let mut far = f32::MAX;
+        for axis in 0..3 {
+            let inverse = 1.0 / self.direction[axis];
+            let mut t0 = (aabb.min[axis] - self.origin[axis]) * inverse;
+            let mut t1 = (aabb.max[axis] - self.origin[axis]) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            near = near.max(t0);
+            far = far.min(t1);
+        }
+        (near <= far && far >= 0.0).then_some(near.max(0.0))
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context};
+
+use crate::paths;
+use crate::source::Row;
+
+// Recordings are text, one sample per line after a header naming the format
+// and its version:
+//
+//   lolitop-recording 2
+//   <unix time in ms> <row> <count> <counters>... <row> <count> <counters>...
+//
+// A row is named like `cpu3`, with the ten /proc/stat counters user through
+// guest_nice, or `meminfo`, with MemTotal, MemAvailable, SwapTotal and
+// SwapFree in kB. When the same row with as many counters was in the line
+// before, the counters are the difference to it, which keeps the numbers
+// short. Otherwise they are the counters as read. Every file starts over with
+// full counters, so rotated files can be read on their own.
+//
+// Version 1 had no counts, every row had the ten /proc/stat counters.
+pub const FORMAT: &str = "lolitop-recording";
+pub const VERSION: u32 = 2;
+
+// Counters per row in version 1 recordings
+const V1_COUNTERS: usize = 10;
+
+// Where Ctrl+R records to when no path is configured
+pub fn default_path() -> Option<PathBuf> {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    paths::data_dir().map(|dir| dir.join("recordings").join(format!("{}.rec", seconds)))
+}
+
+// Writes raw samples to `path`. Once the file grows past `max_size` bytes it
+// is renamed to `path.1`, older ones move up to `path.2` and so on, and at
+// most `max_files` of those are kept.
+pub struct Recorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    written: u64,
+    max_size: u64,
+    max_files: usize,
+    previous: HashMap<String, Vec<u64>>,
+}
+
+impl Recorder {
+    pub fn start(path: &Path, max_size: u64, max_files: usize) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        // An earlier recording at the same path goes into the rotation
+        // rather than being overwritten
+        if path.exists() {
+            rotate(path, max_files)?;
+        }
+        let (writer, written) = create(path)?;
+        Ok(Recorder {
+            path: path.to_path_buf(),
+            writer,
+            written,
+            max_size,
+            max_files,
+            previous: HashMap::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Writes one sample, the counters of every row read at `time`
+    pub fn record<'a>(
+        &mut self,
+        time: SystemTime,
+        rows: impl IntoIterator<Item = (&'a str, &'a [u64])>,
+    ) -> anyhow::Result<()> {
+        let millis = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let mut line = millis.to_string();
+        let mut previous = HashMap::new();
+        for (row, counters) in rows {
+            line.push(' ');
+            line.push_str(&format!("{} {}", row, counters.len()));
+            match self.previous.get(row) {
+                Some(before) if before.len() == counters.len() => {
+                    for (counter, before) in counters.iter().zip(before) {
+                        // Counters go backwards when a core is taken offline
+                        line.push_str(&format!(" {}", *counter as i64 - *before as i64));
+                    }
+                }
+                _ => {
+                    for counter in counters {
+                        line.push_str(&format!(" {}", counter));
+                    }
+                }
+            }
+            previous.insert(row.to_string(), counters.to_vec());
+        }
+        line.push('\n');
+        self.previous = previous;
+
+        // A line at a time, so a crash loses at most the last sample
+        self.writer
+            .write_all(line.as_bytes())
+            .and_then(|()| self.writer.flush())
+            .with_context(|| format!("failed to write {}", self.path.display()))?;
+        self.written += line.len() as u64;
+
+        if self.written >= self.max_size {
+            rotate(&self.path, self.max_files)?;
+            (self.writer, self.written) = create(&self.path)?;
+            self.previous.clear();
+        }
+        Ok(())
+    }
+}
+
+// One line of a recording
+pub struct Sample {
+    // Since the Unix epoch
+    pub time: Duration,
+    pub rows: Vec<Row>,
+}
+
+// Reads a whole recording, with the differences added back up
+pub fn read(path: &Path) -> anyhow::Result<Vec<Sample>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut lines = BufReader::new(file).lines();
+    let header = lines
+        .next()
+        .transpose()
+        .with_context(|| format!("failed to read {}", path.display()))?
+        .unwrap_or_default();
+    let version = match header.split_once(' ') {
+        Some((FORMAT, version)) => match version.parse() {
+            Ok(version @ 1..=VERSION) => version,
+            _ => bail!(
+                "{} is a version {} recording, this lolitop reads up to version {}",
+                path.display(),
+                version,
+                VERSION
+            ),
+        },
+        _ => bail!("{} is not a lolitop recording", path.display()),
+    };
+
+    let mut samples: Vec<Sample> = Vec::new();
+    for (number, line) in lines.enumerate() {
+        let line = line.with_context(|| format!("failed to read {}", path.display()))?;
+        // After the header, and counting from 1
+        let number = number + 2;
+        let mut sample = parse_line(&line, version, samples.last())
+            .with_context(|| format!("{}:{}: invalid sample", path.display(), number))?;
+        // The clock may have been set back while recording
+        if let Some(last) = samples.last() {
+            sample.time = sample.time.max(last.time);
+        }
+        samples.push(sample);
+    }
+    Ok(samples)
+}
+
+fn parse_line(line: &str, version: u32, previous: Option<&Sample>) -> anyhow::Result<Sample> {
+    let mut words = line.split_whitespace();
+    let millis: u64 = words.next().context("empty line")?.parse()?;
+    let mut rows = vec![];
+    while let Some(row) = words.next() {
+        let count = match version {
+            1 => V1_COUNTERS,
+            _ => words
+                .next()
+                .with_context(|| format!("{} has no count", row))?
+                .parse()?,
+        };
+        let before = previous.and_then(|previous| {
+            previous
+                .rows
+                .iter()
+                .find(|(id, counters)| id == row && counters.len() == count)
+                .map(|(_, counters)| counters)
+        });
+        let mut counters = Vec::with_capacity(count);
+        for i in 0..count {
+            let word = words
+                .next()
+                .with_context(|| format!("{} has too few counters", row))?;
+            counters.push(match before {
+                Some(before) => before[i].saturating_add_signed(word.parse()?),
+                None => word.parse()?,
+            });
+        }
+        rows.push((row.to_string(), counters));
+    }
+    Ok(Sample {
+        time: Duration::from_millis(millis),
+        rows,
+    })
+}
+
+fn create(path: &Path) -> anyhow::Result<(BufWriter<File>, u64)> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let header = format!("{} {}\n", FORMAT, VERSION);
+    // Flushed right away, so even a file without samples yet can be read
+    writer
+        .write_all(header.as_bytes())
+        .and_then(|()| writer.flush())
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok((writer, header.len() as u64))
+}
+
+// `path.N` for the Nth newest rotated file
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+// Moves `path` to `path.1`, `path.1` to `path.2` and so on, dropping what
+// would go past `max_files`
+fn rotate(path: &Path, max_files: usize) -> anyhow::Result<()> {
+    if max_files == 0 {
+        return fs::remove_file(path)
+            .with_context(|| format!("failed to remove {}", path.display()));
+    }
+    let oldest = rotated(path, max_files);
+    if oldest.exists() {
+        fs::remove_file(&oldest)
+            .with_context(|| format!("failed to remove {}", oldest.display()))?;
+    }
+    for n in (1..max_files).rev() {
+        let from = rotated(path, n);
+        if from.exists() {
+            let to = rotated(path, n + 1);
+            fs::rename(&from, &to).with_context(|| {
+                format!("failed to rename {} to {}", from.display(), to.display())
+            })?;
+        }
+    }
+    let to = rotated(path, 1);
+    fs::rename(path, &to)
+        .with_context(|| format!("failed to rename {} to {}", path.display(), to.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory for the files of one test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lolitop-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn at(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    fn counters(start: u64) -> Vec<u64> {
+        (0..10).map(|i| start + i * 7).collect()
+    }
+
+    fn assert_rows(sample: &Sample, rows: &[(&str, Vec<u64>)]) {
+        let read = sample
+            .rows
+            .iter()
+            .map(|(row, counters)| (row.as_str(), counters.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(read, rows);
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = test_dir("round-trip").join("session.rec");
+        let mut recorder = Recorder::start(&path, u64::MAX, 4).unwrap();
+        let lines = [
+            (1000, vec![("cpu", counters(100)), ("cpu0", counters(50))]),
+            // cpu1 comes online, cpu0 went offline and back with lower counters
+            (
+                1500,
+                vec![
+                    ("cpu", counters(180)),
+                    ("cpu0", counters(20)),
+                    ("cpu1", counters(9)),
+                ],
+            ),
+            (
+                2000,
+                vec![
+                    ("cpu", counters(260)),
+                    ("cpu0", counters(60)),
+                    ("cpu1", counters(40)),
+                    // Rows can have any number of counters
+                    ("meminfo", vec![8000, 6000, 0, 0]),
+                ],
+            ),
+        ];
+        for (millis, rows) in &lines {
+            let rows = rows
+                .iter()
+                .map(|(row, counters)| (*row, counters.as_slice()));
+            recorder.record(at(*millis), rows).unwrap();
+        }
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with(&format!("{} {}\n", FORMAT, VERSION)));
+        // The second line has differences, like the 80 the total moved
+        assert!(contents
+            .lines()
+            .nth(2)
+            .unwrap()
+            .starts_with("1500 cpu 10 80 "));
+
+        let samples = read(&path).unwrap();
+        assert_eq!(samples.len(), lines.len());
+        for (sample, (millis, rows)) in samples.iter().zip(&lines) {
+            assert_eq!(sample.time, Duration::from_millis(*millis));
+            assert_rows(sample, rows);
+        }
+    }
+
+    #[test]
+    fn rotation() {
+        let path = test_dir("rotation").join("session.rec");
+        // Any line goes past the size, so every sample ends up in a file of its own
+        let mut recorder = Recorder::start(&path, 1, 2).unwrap();
+        for n in 0..3 {
+            let rows = [("cpu", counters(100 * (n + 1))), ("cpu0", counters(n))];
+            let rows = rows
+                .iter()
+                .map(|(row, counters)| (*row, counters.as_slice()));
+            recorder.record(at(1000 * (n + 1)), rows).unwrap();
+        }
+
+        // Rotated files start over with full counters, so each reads on its own
+        let newest = read(&rotated(&path, 1)).unwrap();
+        assert_eq!(newest.len(), 1);
+        assert_rows(&newest[0], &[("cpu", counters(300)), ("cpu0", counters(2))]);
+        let older = read(&rotated(&path, 2)).unwrap();
+        assert_rows(&older[0], &[("cpu", counters(200)), ("cpu0", counters(1))]);
+        // Only two are kept
+        assert!(!rotated(&path, 3).exists());
+        assert!(read(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let path = test_dir("version-1").join("session.rec");
+        let lines = [
+            format!("{} 1", FORMAT),
+            "1000 cpu0 1 2 3 4 5 6 7 8 9 10".to_string(),
+            "2000 cpu0 1 1 1 1 1 1 1 1 1 -1".to_string(),
+        ];
+        fs::write(&path, lines.join("\n")).unwrap();
+        let samples = read(&path).unwrap();
+        assert_rows(&samples[0], &[("cpu0", (1..=10).collect())]);
+        assert_rows(&samples[1], &[("cpu0", (2..=10).chain([9]).collect())]);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let path = test_dir("version").join("session.rec");
+        fs::write(&path, format!("{} {}\n", FORMAT, VERSION + 1)).unwrap();
+        assert!(read(&path).is_err());
+        fs::write(&path, "something else\n").unwrap();
+        assert!(read(&path).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use cgmath::Vector2;
+use glyphon::{Buffer, Color, FontSystem, TextArea, TextBounds};
+use winit::event::{ElementState, MouseButton, WindowEvent};
+
+use crate::metrics::SysMetrics;
+use crate::overlay::{Overlay, PANEL};
+use crate::text::{shape, size};
+
+const FONT_SIZE: f32 = 14.0;
+const MARGIN: f32 = 12.0;
+const PADDING: f32 = 10.0;
+const PANEL_HEIGHT: f32 = 56.0;
+const TRACK_HEIGHT: f32 = 6.0;
+const BUTTON_WIDTH: f32 = 64.0;
+
+// x, y, width and height on screen
+type Rect = [f32; 4];
+
+fn contains(rect: Rect, point: Vector2<f32>) -> bool {
+    let [x, y, width, height] = rect;
+    point.x >= x && point.x <= x + width && point.y >= y && point.y <= y + height
+}
+
+// Timeline along the bottom edge while paused. Dragging on it picks the
+// sample shown, the button goes back to live.
+pub struct Scrubber {
+    visible: bool,
+    dragging: bool,
+    cursor: Option<Vector2<f32>>,
+    status: Option<Buffer>,
+    live: Option<Buffer>,
+    text: String,
+    panel: Rect,
+    track: Rect,
+    button: Rect,
+}
+
+impl Default for Scrubber {
+    fn default() -> Self {
+        Scrubber {
+            visible: false,
+            dragging: false,
+            cursor: None,
+            status: None,
+            live: None,
+            text: String::new(),
+            panel: [0.0; 4],
+            track: [0.0; 4],
+            button: [0.0; 4],
+        }
+    }
+}
+
+impl Scrubber {
+    // True when the event was for the scrubber
+    pub fn input(&mut self, event: &WindowEvent, sys_metrics: &mut SysMetrics) -> bool {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = Some(Vector2::new(position.x as f32, position.y as f32));
+                if self.dragging {
+                    self.seek(sys_metrics);
+                }
+                self.dragging
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+                false
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if self.visible => {
+                let Some(cursor) = self.cursor else {
+                    return false;
+                };
+                // A little slack above and below the thin track
+                let [x, y, width, height] = self.track;
+                if contains([x, y - PADDING, width, height + 2.0 * PADDING], cursor) {
+                    self.dragging = true;
+                    self.seek(sys_metrics);
+                } else if contains(self.button, cursor) {
+                    sys_metrics.resume();
+                }
+                contains(self.panel, cursor)
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Left,
+                ..
+            } if self.dragging => {
+                self.dragging = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Oldest sample at the left end of the track, newest at the right
+    fn seek(&self, sys_metrics: &mut SysMetrics) {
+        let (Some(cursor), Some((_, len))) = (self.cursor, sys_metrics.playhead()) else {
+            return;
+        };
+        let [x, _, width, _] = self.track;
+        let position = ((cursor.x - x) / width).clamp(0.0, 1.0);
+        let back = ((1.0 - position) * len.saturating_sub(1) as f32).round() as usize;
+        sys_metrics.seek(back);
+    }
+
+    pub fn update(
+        &mut self,
+        font_system: &mut FontSystem,
+        overlay: &mut Overlay,
+        sys_metrics: &SysMetrics,
+        width: f32,
+        height: f32,
+    ) {
+        let Some((back, len)) = sys_metrics.playhead() else {
+            self.visible = false;
+            self.dragging = false;
+            return;
+        };
+
+        let text = if back == 0 {
+            "Paused".to_string()
+        } else {
+            let seconds = back as f32 / sys_metrics.sample_rate_hz;
+            format!("Paused, {:.1} s ago", seconds)
+        };
+        if text != self.text {
+            self.status = Some(shape(font_system, &text, FONT_SIZE));
+            self.text = text;
+        }
+        if self.live.is_none() {
+            self.live = Some(shape(font_system, "LIVE", FONT_SIZE));
+        }
+
+        self.panel = [
+            MARGIN,
+            height - MARGIN - PANEL_HEIGHT,
+            width - 2.0 * MARGIN,
+            PANEL_HEIGHT,
+        ];
+        let [x, y, panel_width, panel_height] = self.panel;
+        self.button = [
+            x + panel_width - PADDING - BUTTON_WIDTH,
+            y + PADDING,
+            BUTTON_WIDTH,
+            panel_height - 2.0 * PADDING,
+        ];
+        self.track = [
+            x + PADDING,
+            y + panel_height - PADDING - TRACK_HEIGHT,
+            panel_width - 3.0 * PADDING - BUTTON_WIDTH,
+            TRACK_HEIGHT,
+        ];
+
+        overlay.rect(x, y, panel_width, panel_height, PANEL);
+        let [track_x, track_y, track_width, track_height] = self.track;
+        overlay.rect(
+            track_x,
+            track_y,
+            track_width,
+            track_height,
+            [1.0, 1.0, 1.0, 0.15],
+        );
+        let position = if len > 1 {
+            1.0 - back as f32 / (len - 1) as f32
+        } else {
+            1.0
+        };
+        overlay.rect(
+            track_x,
+            track_y,
+            track_width * position,
+            track_height,
+            [1.0, 0.6, 0.2, 0.6],
+        );
+        overlay.rect(
+            track_x + track_width * position - 2.0,
+            track_y - 4.0,
+            4.0,
+            track_height + 8.0,
+            [1.0, 0.8, 0.4, 1.0],
+        );
+        let [button_x, button_y, button_width, button_height] = self.button;
+        overlay.rect(
+            button_x,
+            button_y,
+            button_width,
+            button_height,
+            [0.8, 0.1, 0.1, 0.9],
+        );
+        self.visible = true;
+    }
+
+    pub fn text_areas(&self) -> impl Iterator<Item = TextArea<'_>> {
+        let [x, y, _, _] = self.panel;
+        let status = self.status.as_ref().map(|buffer| TextArea {
+            buffer,
+            left: x + PADDING,
+            top: y + PADDING / 2.0,
+            scale: 1.0,
+            bounds: TextBounds::default(),
+            default_color: Color::rgba(255, 255, 255, 255),
+            custom_glyphs: &[],
+        });
+        // Centered on the button
+        let [button_x, button_y, button_width, button_height] = self.button;
+        let live = self.live.as_ref().map(|buffer| TextArea {
+            buffer,
+            left: button_x + (button_width - size(buffer).0) / 2.0,
+            top: button_y + (button_height - size(buffer).1) / 2.0,
+            scale: 1.0,
+            bounds: TextBounds::default(),
+            default_color: Color::rgba(255, 255, 255, 255),
+            custom_glyphs: &[],
+        });
+        status.into_iter().chain(live).filter(|_| self.visible)
+    }
+}
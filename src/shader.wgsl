@@ -14,6 +14,17 @@ struct Light {
 @group(1) @binding(0)
 var<uniform> light: Light;
 
+struct Bars {
+    max_height: f32,
+    min_height: f32,
+    // Unlit tiles for the heatmap
+    flat: f32,
+    // Index into the colormaps below, see Colormap in metrics.rs
+    colormap: u32,
+}
+@group(2) @binding(0)
+var<uniform> bars: Bars;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) normal: vec3<f32>,
@@ -26,17 +37,26 @@ struct InstanceInput {
     @location(8) model_matrix_3: vec4<f32>,
 }
 
+// Each between 0 and 1, see Visuals in encoding.rs
 struct ValueInput {
-    @location(9) value: f32
+    @location(9) height: f32,
+    @location(10) color: f32,
+    @location(11) width: f32,
+    @location(12) glow: f32,
+    @location(13) highlight: f32,
 }
 
+// Width of a bar at the bottom of its width channel, as in metrics.rs
+const MIN_WIDTH: f32 = 0.25;
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) position: vec4<f32>,
     @location(1) value: f32,
     @location(2) world_normal: vec3<f32>,
     @location(3) world_position: vec3<f32>,
-
+    @location(4) highlight: f32,
+    @location(5) glow: f32,
 }
 
 @vertex
@@ -52,31 +72,30 @@ fn vs_main(
         instance.model_matrix_3,
     );
 
-    let scale_factor = instance_value.value * 2.0;
-    let scaling = mat4x4<f32>(
-        scale_factor, 0.0, 0.0, 0.0,
-        0.0, scale_factor, 0.0, 0.0,
-        0.0, 0.0, scale_factor, 0.0,
-        0.0, 0.0, 0.0, 1.0
+    // Bars stand on the floor: the cube spans -1..1, so move its base to y = 0
+    // and stretch it up to the bar height
+    let height = max(instance_value.height * bars.max_height, bars.min_height);
+    let half_height = max(height * 0.5, 0.0001);
+    let width = mix(MIN_WIDTH, 1.0, instance_value.width);
+    let local_position = vec3<f32>(
+        model.position.x * width,
+        (model.position.y + 1.0) * half_height,
+        model.position.z * width,
     );
+    let world_position = model_matrix * vec4<f32>(local_position, 1.0);
 
-    let height_scaling = mat4x4<f32>(
-        1.0, 0.0, 0.0, 0.0,
-        0.0, scale_factor, 0.0, 0.0,
-        0.0, 0.0, 1.0, 0.0,
-        0.0, 0.0, 0.0, 1.0
-    );
+    // Normals go through the inverse transpose of the scaling
+    let scaled_normal = normalize(vec3<f32>(model.normal.x / width, model.normal.y / half_height, model.normal.z / width));
 
     var out: VertexOutput;
-
-    out.world_normal = model.normal;
-    out.clip_position = camera.view_proj * model_matrix * height_scaling * vec4<f32>(model.position, 1.0);
-    out.position = instance_value.value * model_matrix * vec4<f32>(model.position, 1.0);
-
-    var world_position: vec4<f32> = model_matrix * vec4<f32>(model.position, 1.0);
+    out.world_normal = normalize((model_matrix * vec4<f32>(scaled_normal, 0.0)).xyz);
+    out.clip_position = camera.view_proj * world_position;
+    out.position = world_position;
     out.world_position = world_position.xyz;
 
-    out.value = instance_value.value;
+    out.value = instance_value.color;
+    out.highlight = instance_value.highlight;
+    out.glow = instance_value.glow;
     return out;
 }
 
@@ -125,7 +144,13 @@ fn constant_color(value: f32) -> vec4f {
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
 
-    let object_color: vec4<f32> = to_fire(in.value);
+    var object_color: vec4<f32>;
+    switch bars.colormap {
+        case 1u: { object_color = to_rainbow(in.value); }
+        case 2u: { object_color = to_cosmic(in.value); }
+        case 3u: { object_color = constant_color(in.value); }
+        default: { object_color = to_fire(in.value); }
+    }
 
     // let object_color: vec4<f32> = vec4<f32>(1.0, 1.0, 1.0, 1.0);
 
@@ -142,7 +167,17 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let diffuse_color = light.color * diffuse_strength * falloff;
 
 
-    let result = (ambient_color + diffuse_color) * object_color.xyz;
+    var result = (ambient_color + diffuse_color) * object_color.xyz;
+    if bars.flat > 0.5 {
+        // Keep idle tiles visible against the background
+        result = max(object_color.xyz, vec3<f32>(0.06));
+    }
+
+    // Glowing bars light up in their own color, lifted so dark colors show
+    result = result + mix(object_color.xyz, vec3<f32>(1.0), 0.5) * in.glow * 0.8;
+
+    // Hovered and selected bars are tinted
+    result = mix(result, vec3<f32>(0.4, 0.8, 1.0), in.highlight * 0.5);
 
     return vec4<f32>(result, object_color.a);
 }
\ No newline at end of file
@@ -0,0 +1,374 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use anyhow::{ensure, Context};
+
+use crate::cli::Options;
+use crate::cpu::{CPUMetrics, CpuSelection};
+use crate::memory::{MemoryMetrics, ProcMeminfo, MEMINFO};
+use crate::recording::{self, Sample};
+use crate::synthetic::Synthetic;
+
+// The ten /proc/stat counters of a CPU row, user through guest_nice
+pub type Counters = [u64; 10];
+
+// A row as a counter source reads it, like `cpu3` with its ten /proc/stat
+// counters or `meminfo` with its four
+pub type Row = (String, Vec<u64>);
+
+// One thing an instance reports, like its usage or its clock
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Channel {
+    pub name: &'static str,
+    pub unit: &'static str,
+    // What maps to 0 and 1 when the channel drives how bars look. Without one
+    // the highest value of the group maps to 1.
+    pub range: Option<(f32, f32)>,
+}
+
+// A group of instances in the scene, like the CPU cores or the memory. Every
+// instance is a bar as high as its first channel, which goes from 0 to 1.
+pub trait MetricSource {
+    // Like `cpu` or `memory`
+    fn name(&self) -> &str;
+
+    fn channels(&self) -> &[Channel];
+
+    // What the instances are called, like `cpu3`
+    fn instances(&self) -> Vec<&str>;
+
+    fn sample(&mut self);
+
+    // The first channel of every instance, `delta` of the way from the sample
+    // before the newest to the newest one
+    fn values(&self, delta: f32) -> Vec<f32>;
+
+    // The first channel of one instance per sample period, oldest first
+    fn history(&self, instance: usize) -> &VecDeque<f32>;
+
+    // The newest value of a channel, in its unit
+    fn value(&self, instance: usize, channel: usize) -> Option<f32>;
+
+    // More about an instance for its tooltip, a line each
+    fn details(&self, _instance: usize) -> Vec<String> {
+        vec![]
+    }
+
+    // The rows this source was last sampled from, for recording
+    fn raw(&self) -> Vec<Row> {
+        vec![]
+    }
+
+    // Set when the source plays back a recording
+    fn replay(&self) -> Option<SharedReplay> {
+        None
+    }
+
+    // For what only one kind of source has, like the CPU selection
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+// Everything to show, in the order the groups are laid out. Made up CPUs
+// come without memory, and so do recordings made before memory was recorded.
+// Fails when the recording to replay can't be read.
+pub fn sources(
+    options: &Options,
+    selection: CpuSelection,
+) -> anyhow::Result<Vec<Box<dyn MetricSource>>> {
+    let (counters, meminfo): (Box<dyn CounterSource>, Option<Box<dyn CounterSource>>) =
+        match &options.replay {
+            Some(path) => {
+                let mut replay = Replay::open(path)?;
+                replay.speed = options.speed;
+                replay.looping = options.looping;
+                let has_memory = replay.has_row(MEMINFO);
+                let replay = Rc::new(RefCell::new(replay));
+                let meminfo = has_memory.then(|| Box::new(replay.clone()) as Box<_>);
+                (Box::new(replay), meminfo)
+            }
+            None => match options.synthetic {
+                Some(scenario) => (Box::new(Synthetic::new(scenario, options.cores)), None),
+                None => (
+                    Box::new(ProcStat::new(&options.proc_root)),
+                    Some(Box::new(ProcMeminfo::new(&options.proc_root))),
+                ),
+            },
+        };
+    let mut sources: Vec<Box<dyn MetricSource>> = vec![Box::new(CPUMetrics::new(
+        options.proc_root.clone(),
+        counters,
+        selection,
+    ))];
+    if let Some(meminfo) = meminfo {
+        sources.push(Box::new(MemoryMetrics::new(meminfo)));
+    }
+    Ok(sources)
+}
+
+// Where CPUMetrics and MemoryMetrics get their counters from
+pub trait CounterSource {
+    // The counters of every row as of now, like `cpu` and `cpu0` of /proc/stat
+    // or `meminfo`
+    fn sample(&mut self) -> anyhow::Result<Vec<Row>>;
+
+    // True when the counters are this machine's, as opposed to recorded or
+    // made up ones, so its clocks and temperatures go with them
+    fn is_live(&self) -> bool {
+        false
+    }
+
+    // Set when the counters come from a recording
+    fn replay(&self) -> Option<SharedReplay> {
+        None
+    }
+}
+
+// The live counters from /proc/stat
+pub struct ProcStat {
+    path: PathBuf,
+}
+
+impl ProcStat {
+    pub fn new(proc_root: &Path) -> Self {
+        ProcStat {
+            path: proc_root.join("stat"),
+        }
+    }
+}
+
+impl CounterSource for ProcStat {
+    fn sample(&mut self) -> anyhow::Result<Vec<Row>> {
+        let stat = fs::read_to_string(&self.path)
+            .with_context(|| format!("failed to read {}", self.path.display()))?;
+        Ok(stat.lines().filter_map(parse_row).collect())
+    }
+
+    fn is_live(&self) -> bool {
+        true
+    }
+}
+
+fn parse_row(row: &str) -> Option<Row> {
+    let mut words = row.split_whitespace();
+    let cpu_id = words.next()?;
+    // The lines we are interested in are the ones that start with cpu, the
+    // bare `cpu` one sums up all cores
+    if !cpu_id.starts_with("cpu") {
+        return None;
+    }
+    let mut counters = Counters::default();
+    for counter in &mut counters {
+        *counter = words.next()?.parse().ok()?;
+    }
+    Some((cpu_id.to_string(), counters.to_vec()))
+}
+
+// Plays a recording back as if it was sampled live. The counters are
+// interpolated between the recorded samples, so any sample rate and speed
+// give the usage the recording saw.
+pub struct Replay {
+    path: PathBuf,
+    samples: Vec<Sample>,
+    // Into the recording, from its first sample
+    position: Duration,
+    pub speed: f32,
+    pub looping: bool,
+    last_sample: Option<Instant>,
+}
+
+impl Replay {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let samples = recording::read(path)?;
+        ensure!(!samples.is_empty(), "{} has no samples", path.display());
+        Ok(Replay {
+            path: path.to_path_buf(),
+            samples,
+            position: Duration::ZERO,
+            speed: 1.0,
+            looping: false,
+            last_sample: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.samples[self.samples.len() - 1].time - self.samples[0].time
+    }
+
+    pub fn position(&self) -> Duration {
+        self.position
+    }
+
+    pub fn seek(&mut self, position: Duration) {
+        self.position = position.min(self.duration());
+    }
+
+    // Whether any sample has `row`, like `meminfo`
+    pub fn has_row(&self, row: &str) -> bool {
+        self.samples
+            .iter()
+            .any(|sample| sample.rows.iter().any(|(id, _)| id == row))
+    }
+
+    // Moves the playhead on by the time since the last sample, times the speed
+    fn advance(&mut self, now: Instant) {
+        let elapsed = self.last_sample.map_or(Duration::ZERO, |last| now - last);
+        self.last_sample = Some(now);
+        let duration = self.duration();
+        let position = self.position + elapsed.mul_f32(self.speed);
+        self.position = if position <= duration {
+            position
+        } else if self.looping && !duration.is_zero() {
+            Duration::from_nanos((position.as_nanos() % duration.as_nanos()) as u64)
+        } else {
+            duration
+        };
+    }
+}
+
+impl CounterSource for Replay {
+    fn sample(&mut self) -> anyhow::Result<Vec<Row>> {
+        self.advance(Instant::now());
+        let time = self.samples[0].time + self.position;
+        // The last sample at or before the playhead and the one after it
+        let next = self.samples.partition_point(|sample| sample.time <= time);
+        let before = &self.samples[next.saturating_sub(1)];
+        let Some(after) = self.samples.get(next) else {
+            return Ok(before.rows.clone());
+        };
+        let t = (time - before.time).as_secs_f64() / (after.time - before.time).as_secs_f64();
+        Ok(before
+            .rows
+            .iter()
+            .map(|(row, counters)| {
+                let next = after
+                    .rows
+                    .iter()
+                    .find(|(id, next)| id == row && next.len() == counters.len());
+                let Some((_, next)) = next else {
+                    return (row.clone(), counters.clone());
+                };
+                let interpolated = counters
+                    .iter()
+                    .zip(next)
+                    .map(|(&counter, &next)| {
+                        (counter as f64 + (next as f64 - counter as f64) * t) as u64
+                    })
+                    .collect();
+                (row.clone(), interpolated)
+            })
+            .collect())
+    }
+}
+
+// One replay feeding several sources, so they all play the same part of the
+// recording. The first to sample in a frame moves the playhead on, the others
+// sample it where it already is.
+pub type SharedReplay = Rc<RefCell<Replay>>;
+
+impl CounterSource for SharedReplay {
+    fn sample(&mut self) -> anyhow::Result<Vec<Row>> {
+        self.borrow_mut().sample()
+    }
+
+    fn replay(&self) -> Option<SharedReplay> {
+        Some(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::recording::Recorder;
+
+    fn recording_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lolitop-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("session.rec")
+    }
+
+    // A recording of one row going from `from` to `to` counters in two
+    // seconds
+    fn replay(name: &str, from: u64, to: u64) -> Replay {
+        let path = recording_path(name);
+        let mut recorder = Recorder::start(&path, u64::MAX, 1).unwrap();
+        for (seconds, counter) in [(10, from), (12, to)] {
+            let time = UNIX_EPOCH + Duration::from_secs(seconds);
+            recorder
+                .record(time, [("cpu0", &[counter; 10][..])])
+                .unwrap();
+        }
+        Replay::open(&path).unwrap()
+    }
+
+    fn first_counter(replay: &mut Replay) -> u64 {
+        let rows = CounterSource::sample(replay).unwrap();
+        assert_eq!(rows[0].0, "cpu0");
+        rows[0].1[0]
+    }
+
+    #[test]
+    fn interpolates_between_samples() {
+        let mut replay = replay("interpolate", 1000, 2000);
+        assert_eq!(replay.duration(), Duration::from_secs(2));
+        assert_eq!(first_counter(&mut replay), 1000);
+        replay.seek(Duration::from_millis(500));
+        assert_eq!(first_counter(&mut replay), 1250);
+        replay.seek(Duration::from_secs(2));
+        assert_eq!(first_counter(&mut replay), 2000);
+    }
+
+    #[test]
+    fn seek_stays_within_the_recording() {
+        let mut replay = replay("seek", 0, 100);
+        replay.seek(Duration::from_secs(60));
+        assert_eq!(replay.position(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn playback_stops_or_loops_at_the_end() {
+        let start = Instant::now();
+        let mut replay = replay("loop", 0, 100);
+        replay.speed = 2.0;
+        replay.advance(start);
+        replay.advance(start + Duration::from_millis(1500));
+        assert_eq!(replay.position(), Duration::from_secs(2));
+
+        replay.seek(Duration::ZERO);
+        replay.looping = true;
+        replay.advance(start + Duration::from_millis(3000));
+        // Three seconds into the two second recording
+        assert_eq!(replay.position(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn memory_plays_back_from_the_same_replay() {
+        let path = recording_path("memory");
+        let gib = 1024 * 1024;
+        let meminfo = vec![8 * gib, 2 * gib, 0, 0];
+        let mut recorder = Recorder::start(&path, u64::MAX, 1).unwrap();
+        let time = UNIX_EPOCH + Duration::from_secs(10);
+        let rows = [("cpu0", &[0; 10][..]), (MEMINFO, &meminfo)];
+        recorder.record(time, rows).unwrap();
+
+        let replay = Replay::open(&path).unwrap();
+        assert!(replay.has_row(MEMINFO));
+        let replay = Rc::new(RefCell::new(replay));
+        let memory = MemoryMetrics::new(Box::new(replay.clone()));
+        // 6 of 8 GiB used
+        assert_eq!(memory.value(0, 1), Some(6.0));
+        assert_eq!(memory.raw(), vec![(MEMINFO.to_string(), meminfo)]);
+        assert!(memory.replay().is_some());
+    }
+}
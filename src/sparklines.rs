@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use cgmath::Vector2;
+
+use crate::metrics::SysMetrics;
+use crate::overlay::{Color, Overlay};
+
+const MARGIN: f32 = 12.0;
+const GAP: f32 = 4.0;
+const BACKGROUND: Color = [0.0, 0.0, 0.0, 0.5];
+const FILL: Color = [1.0, 0.4, 0.1, 0.25];
+const LINE: Color = [1.0, 0.7, 0.2, 0.9];
+const GUIDE: Color = [1.0, 1.0, 1.0, 0.12];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum SparklineLayout {
+    // A grid of small graphs in the bottom left corner
+    Corner,
+    // One graph per core stacked along the right edge
+    Column,
+    Hidden,
+}
+
+impl SparklineLayout {
+    pub const ALL: [SparklineLayout; 3] = [
+        SparklineLayout::Corner,
+        SparklineLayout::Column,
+        SparklineLayout::Hidden,
+    ];
+
+    pub fn next(self) -> Self {
+        match self {
+            SparklineLayout::Corner => SparklineLayout::Column,
+            SparklineLayout::Column => SparklineLayout::Hidden,
+            SparklineLayout::Hidden => SparklineLayout::Corner,
+        }
+    }
+}
+
+impl fmt::Display for SparklineLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SparklineLayout::Corner => "corner",
+            SparklineLayout::Column => "column",
+            SparklineLayout::Hidden => "hidden",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SparklineLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        SparklineLayout::ALL
+            .into_iter()
+            .find(|layout| layout.to_string() == name)
+            .ok_or_else(|| anyhow::anyhow!("unknown sparkline layout '{}'", name))
+    }
+}
+
+impl TryFrom<String> for SparklineLayout {
+    type Error = anyhow::Error;
+
+    fn try_from(name: String) -> anyhow::Result<Self> {
+        name.parse()
+    }
+}
+
+pub struct Sparklines {
+    pub layout: SparklineLayout,
+    // How much history each graph covers
+    pub seconds: f32,
+}
+
+impl Default for Sparklines {
+    fn default() -> Self {
+        Sparklines {
+            layout: SparklineLayout::Hidden,
+            seconds: 30.0,
+        }
+    }
+}
+
+impl Sparklines {
+    pub fn draw(&self, overlay: &mut Overlay, sys_metrics: &SysMetrics, width: f32, height: f32) {
+        let ncpus = sys_metrics.instances.len();
+        if ncpus == 0 {
+            return;
+        }
+        let (columns, graph_width, graph_height, left, top) = match self.layout {
+            SparklineLayout::Hidden => return,
+            SparklineLayout::Corner => {
+                let columns = (ncpus as f32).sqrt().ceil() as usize;
+                let rows = ncpus.div_ceil(columns);
+                let (graph_width, graph_height) = (120.0, 32.0);
+                let panel_height = rows as f32 * (graph_height + GAP) - GAP;
+                (
+                    columns,
+                    graph_width,
+                    graph_height,
+                    MARGIN,
+                    height - MARGIN - panel_height,
+                )
+            }
+            SparklineLayout::Column => {
+                let graph_width = 180.0;
+                let graph_height = ((height - 2.0 * MARGIN) / ncpus as f32 - GAP).clamp(4.0, 48.0);
+                (
+                    1,
+                    graph_width,
+                    graph_height,
+                    width - MARGIN - graph_width,
+                    MARGIN,
+                )
+            }
+        };
+
+        let samples = ((self.seconds * sys_metrics.sample_rate_hz).ceil() as usize).max(2);
+        for cpu in 0..ncpus {
+            let x = left + (cpu % columns) as f32 * (graph_width + GAP);
+            let y = top + (cpu / columns) as f32 * (graph_height + GAP);
+            graph(
+                overlay,
+                sys_metrics.history(cpu),
+                samples,
+                x,
+                y,
+                graph_width,
+                graph_height,
+            );
+        }
+    }
+}
+
+// The last `samples` values of `history` as a filled line graph, newest at the
+// right edge
+pub fn graph(
+    overlay: &mut Overlay,
+    history: &VecDeque<f32>,
+    samples: usize,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+) {
+    overlay.rect(x, y, width, height, BACKGROUND);
+    overlay.rect(x, y + height / 2.0, width, 1.0, GUIDE);
+
+    let step = width / (samples.max(2) - 1) as f32;
+    let points = history
+        .iter()
+        .rev()
+        .take(samples)
+        .enumerate()
+        .map(|(i, value)| {
+            Vector2::new(
+                x + width - i as f32 * step,
+                y + height * (1.0 - value.clamp(0.0, 1.0)),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let bottom = y + height;
+    for segment in points.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        overlay.quad(
+            [a, b, Vector2::new(b.x, bottom), Vector2::new(a.x, bottom)],
+            FILL,
+        );
+    }
+    overlay.polyline(&points, 1.5, LINE);
+}
@@ -1,14 +1,35 @@
 use std::iter;
-use std::time::Instant;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 use cgmath::Rotation3;
 use wgpu::util::DeviceExt;
-use winit::keyboard::NamedKey;
+use winit::event::*;
+use winit::keyboard::ModifiersState;
 use winit::window::Window;
-use winit::{event::*, keyboard::Key};
 
-use crate::camera::CameraController;
-use crate::metrics::InstanceRaw;
+use crate::bindings::{Action, Bindings};
+use crate::camera::{CameraController, Projection};
+use crate::cli::Options;
+use crate::config::{Config, ConfigWatcher};
+use crate::cpu::{CPUMetrics, CpuSelection};
+use crate::drilldown::DrillDown;
+use crate::ground::Ground;
+use crate::ground::GroundMode;
+use crate::help::Help;
+use crate::labels::Labels;
+use crate::layout::Layout;
+use crate::menu::{self, Menu, MenuInput, Setting};
+use crate::mesh::Shape;
+use crate::metrics::{Colormap, InstanceRaw};
+use crate::overlay::Overlay;
+use crate::recording::{self, Recorder};
+use crate::scrubber::Scrubber;
+use crate::source::{self, SharedReplay};
+use crate::sparklines::{SparklineLayout, Sparklines};
+use crate::tooltip::Tooltip;
+use crate::tour::{Tour, TourMode};
+use crate::views::{View, Views};
 use crate::{camera::Camera, metrics::SysMetrics};
 use crate::{model, text};
 
@@ -16,6 +37,9 @@ use crate::light::LightUniform;
 
 use std::sync::Arc;
 
+// Until the config says otherwise
+const DEFAULT_SAMPLE_COUNT: u32 = 4;
+
 pub struct State<'a> {
     surface: wgpu::Surface<'a>,
     device: wgpu::Device,
@@ -23,7 +47,11 @@ pub struct State<'a> {
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
+    render_pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
+    // MSAA samples per pixel, and the counts the adapter can do
+    sample_count: u32,
+    sample_counts: Vec<u32>,
 
     depth_buffer: wgpu::Texture,
     msaa_buffer: wgpu::TextureView,
@@ -37,19 +65,56 @@ pub struct State<'a> {
     camera_controller: CameraController,
     sys_metrics: SysMetrics,
 
+    // Meshes to cycle through for the instances
+    shapes: Vec<Shape>,
+    shape: usize,
+
+    // Layouts to cycle through
+    layouts: Vec<Layout>,
+    layout: usize,
+
+    // Ground plane and reference levels
+    ground: Ground,
+
+    // 2D shapes drawn over the scene
+    overlay: Overlay,
+    sparklines: Sparklines,
+
     // Text
     main_text: text::Text,
+    labels: Labels,
+    tooltip: Tooltip,
+    scrubber: Scrubber,
+    help: Help,
+    menu: Menu,
+    drilldown: DrillDown,
+    tour: Tour,
+
+    // Last known cursor position in window pixels
+    cursor: Option<cgmath::Vector2<f32>>,
 
     last_frame: Instant,
     is_fullscreen: bool,
     is_transparent: bool,
+    views: Views,
+    // Name of the saved view last switched to
+    view: Option<String>,
+
+    settings: Config,
+    bindings: Bindings,
+    modifiers: ModifiersState,
+    quit: bool,
+    config_watcher: ConfigWatcher,
+    // Shown on screen until the config file is fixed
+    config_error: Option<String>,
+    options: Options,
 }
 
 impl<'a> State<'a> {
-    pub async fn new(window: Window) -> Self {
+    pub async fn new(window: Window, options: &Options) -> anyhow::Result<Self> {
         let size = window.inner_size();
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
+            backends: options.backends,
             ..Default::default()
         });
 
@@ -99,18 +164,53 @@ impl<'a> State<'a> {
         };
         surface.configure(&device, &config);
 
-        let sys_metrics = SysMetrics::new(&device);
+        // Both the color and the depth attachment are multisampled
+        let sample_counts = adapter
+            .get_texture_format_features(surface_format)
+            .flags
+            .supported_sample_counts()
+            .into_iter()
+            .filter(|count| {
+                adapter
+                    .get_texture_format_features(wgpu::TextureFormat::Depth32Float)
+                    .flags
+                    .sample_count_supported(*count)
+            })
+            .collect::<Vec<_>>();
+
+        // Read early, the CPUs to show decide the size of the buffers
+        let config_watcher = ConfigWatcher::new(
+            options.config.clone().or_else(Config::path),
+            options.config.is_some(),
+        );
+        let loaded_config = match config_watcher.load() {
+            // A config asked for on the command line has to load, later
+            // mistakes only show up on screen
+            Err(e) if options.config.is_some() => return Err(e),
+            loaded_config => loaded_config,
+        };
+        let selection = options
+            .metrics
+            .clone()
+            .or_else(|| loaded_config.as_ref().ok()?.sampling.metrics.clone())
+            .unwrap_or_default();
+        let sys_metrics = SysMetrics::new(&device, source::sources(options, selection)?);
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
         let camera_controller = CameraController::new(Camera::new(
             &device,
             config.width as f32,
             config.height as f32,
         ));
 
+        let ground = Ground::new(
+            &device,
+            &camera_controller.camera().bind_group_layout,
+            config.format,
+            DEFAULT_SAMPLE_COUNT,
+        );
+        let (min, max) = sys_metrics.bounds();
+        ground.set_bounds(&queue, min, max, sys_metrics.spacing);
+
         let light_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[wgpu::BindGroupLayoutEntry {
@@ -154,14 +254,99 @@ impl<'a> State<'a> {
                 bind_group_layouts: &[
                     &camera_controller.camera().bind_group_layout,
                     &light_bind_group_layout,
+                    &sys_metrics.bar_bind_group_layout,
                 ],
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        let render_pipeline = Self::render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            config.format,
+            DEFAULT_SAMPLE_COUNT,
+        );
+
+        let depth_buffer = Self::depth_buffer(&device, &config, DEFAULT_SAMPLE_COUNT);
+        let msaa_buffer = Self::msaa_buffer(&device, &config, DEFAULT_SAMPLE_COUNT);
+
+        let overlay = Overlay::new(&device, &queue, surface_format, size.width, size.height);
+
+        let main_text =
+            text::Text::init_text(&device, &queue, surface_format, size.width, size.height);
+
+        let mut state = Self {
+            surface,
+            device,
+            queue,
+            config,
+            size,
+            render_pipeline_layout,
+            render_pipeline,
+            sample_count: DEFAULT_SAMPLE_COUNT,
+            sample_counts,
+
+            camera_controller,
+            sys_metrics,
+            shapes: Shape::available(),
+            shape: 0,
+            layouts: Layout::available(),
+            layout: 0,
+            ground,
+            window,
+            last_frame: Instant::now(),
+            light_uniform,
+            light_buffer,
+            light_bind_group,
+            depth_buffer,
+            msaa_buffer,
+            overlay,
+            sparklines: Sparklines::default(),
+            main_text,
+            labels: Labels::default(),
+            tooltip: Tooltip::default(),
+            scrubber: Scrubber::default(),
+            help: Help::default(),
+            menu: Menu::default(),
+            drilldown: DrillDown::default(),
+            tour: Tour::default(),
+            cursor: None,
+            is_fullscreen: false,
+            is_transparent: false,
+            views: Views::load(),
+            view: None,
+            settings: Config::default(),
+            bindings: Bindings::default(),
+            modifiers: ModifiersState::empty(),
+            quit: false,
+            config_watcher,
+            config_error: None,
+            options: options.clone(),
+        };
+        match loaded_config {
+            Ok(config) => state.apply_config(config),
+            Err(e) => state.report_config_error(e),
+        }
+        let last = state.views.last.clone();
+        state.apply_view(&last, false);
+        state.apply_options(options)?;
+        Ok(state)
+    }
+
+    fn render_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             cache: None,
             label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
                 module: &shader,
                 entry_point: Some("vs_main"),
@@ -176,7 +361,7 @@ impl<'a> State<'a> {
                 module: &shader,
                 entry_point: Some("fs_main"),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
+                    format,
                     blend: Some(wgpu::BlendState {
                         color: wgpu::BlendComponent::REPLACE,
                         alpha: wgpu::BlendComponent::OVER,
@@ -202,59 +387,382 @@ impl<'a> State<'a> {
                 bias: wgpu::DepthBiasState::default(),
             }),
             multisample: wgpu::MultisampleState {
-                count: 4,
+                count: sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
             // If the pipeline will be used with a multiview render pass, this
             // indicates how many array layers the attachments will have.
             multiview: None,
-        });
+        })
+    }
 
-        let light_uniform = LightUniform {
-            position: [2.0, 2.0, 2.0],
-            _padding: 0,
-            color: [1.0, 1.0, 1.0],
-            _padding2: 0,
+    // Applies a freshly loaded config, keeping what the command line set
+    fn apply_config(&mut self, config: Config) {
+        self.config_error = None;
+        // What can also be changed with keys is only applied when the file
+        // changed it, so saving an unrelated setting doesn't undo the keys
+        let previous = std::mem::replace(&mut self.settings, config.clone());
+
+        if config.sampling.rate != previous.sampling.rate {
+            if let Some(rate) = config.sampling.rate {
+                self.sys_metrics.sample_rate_hz = rate;
+            }
+        }
+        // The command line wins over the file
+        if config.sampling.metrics != previous.sampling.metrics && self.options.metrics.is_none() {
+            let selection = config.sampling.metrics.clone().unwrap_or_default();
+            if selection != self.cpu_selection() {
+                self.set_cpu_selection(selection);
+            }
+        }
+        if config.encoding != previous.encoding {
+            if let Err(e) = self.sys_metrics.set_encodings(&config.encoding) {
+                self.report_config_error(e);
+            }
+        }
+        if config.render.colormap != previous.render.colormap {
+            if let Some(colormap) = config.render.colormap {
+                self.sys_metrics.colormap = colormap;
+            }
+        }
+        if config.layout.name != previous.layout.name {
+            if let Some(name) = &config.layout.name {
+                match self
+                    .layouts
+                    .iter()
+                    .position(|layout| layout.to_string() == *name)
+                {
+                    Some(index) => {
+                        self.layout = index;
+                        let layout = self.current_layout();
+                        self.sys_metrics.set_layout(&layout);
+                    }
+                    None => {
+                        self.config_error = Some(format!("layout.name: unknown layout '{}'", name))
+                    }
+                }
+            }
+        }
+        let effects = &config.effects;
+        if effects.ground != previous.effects.ground {
+            if let Some(mode) = effects.ground {
+                self.ground.mode = mode;
+            }
+        }
+        if effects.labels != previous.effects.labels {
+            if let Some(enabled) = effects.labels {
+                self.labels.enabled = enabled;
+            }
+        }
+        if effects.sparklines != previous.effects.sparklines {
+            if let Some(layout) = effects.sparklines {
+                self.sparklines.layout = layout;
+            }
+        }
+        if effects.waterfall != previous.effects.waterfall
+            && effects
+                .waterfall
+                .is_some_and(|enabled| enabled != self.sys_metrics.waterfall.enabled)
+        {
+            self.toggle_waterfall();
+        }
+
+        if config.render.msaa != self.sample_count {
+            if self.sample_counts.contains(&config.render.msaa) {
+                self.set_sample_count(config.render.msaa);
+            } else {
+                self.config_error = Some(format!(
+                    "render.msaa {} is not supported here, try one of {:?}",
+                    config.render.msaa, self.sample_counts
+                ));
+                log::error!("{}", self.config_error.as_ref().unwrap());
+            }
+        }
+        self.light_uniform.color = config.render.light_color;
+
+        self.main_text.scale = config.text.scale;
+        self.main_text.set_font_size(config.text.size);
+
+        if config.layout.spacing != self.sys_metrics.spacing {
+            self.sys_metrics.spacing = config.layout.spacing;
+            let layout = self.current_layout();
+            self.sys_metrics.set_layout(&layout);
+        }
+
+        match Bindings::new(&config.bindings) {
+            Ok(bindings) => self.bindings = bindings,
+            Err(e) => self.report_config_error(e),
+        }
+
+        self.camera_controller.configure(&config.camera);
+        self.tour.idle_timeout = self.options.screensaver.or(config.screensaver());
+    }
+
+    fn cpu_selection(&self) -> CpuSelection {
+        self.sys_metrics
+            .source::<CPUMetrics>()
+            .map(|cpu_metrics| cpu_metrics.selection().clone())
+            .unwrap_or_default()
+    }
+
+    // The recording being played back, if any
+    fn replay(&self) -> Option<SharedReplay> {
+        self.sys_metrics
+            .sources()
+            .iter()
+            .find_map(|source| source.replay())
+    }
+
+    // The bar buffers are sized for the number of CPUs, so showing other
+    // CPUs means starting over with the metrics
+    fn set_cpu_selection(&mut self, selection: CpuSelection) {
+        // Same sources, so a replay carries on where it was
+        let mut sources = self.sys_metrics.take_sources();
+        for source in &mut sources {
+            if let Some(cpu_metrics) = source.as_any_mut().downcast_mut::<CPUMetrics>() {
+                cpu_metrics.set_selection(selection.clone());
+            }
+        }
+        let mut sys_metrics = SysMetrics::new(&self.device, sources);
+        sys_metrics.sample_rate_hz = self.sys_metrics.sample_rate_hz;
+        sys_metrics.min_bar_height = self.sys_metrics.min_bar_height;
+        sys_metrics.flat = self.sys_metrics.flat;
+        sys_metrics.colormap = self.sys_metrics.colormap;
+        sys_metrics.spacing = self.sys_metrics.spacing;
+        sys_metrics.waterfall.enabled = self.sys_metrics.waterfall.enabled;
+        sys_metrics.recorder = self.sys_metrics.recorder.take();
+        // Reported when the config was applied
+        let _ = sys_metrics.set_encodings(&self.settings.encoding);
+        match self.shapes[self.shape].model() {
+            Ok(model) => sys_metrics.set_model(&self.device, &model),
+            Err(e) => log::error!("{:#}", e),
+        }
+        self.sys_metrics = sys_metrics;
+        let layout = self.current_layout();
+        self.sys_metrics.set_layout(&layout);
+    }
+
+    // The value of each setting, as shown in the menu
+    fn setting_values(&self) -> Vec<String> {
+        let on_off = |enabled: bool| if enabled { "on" } else { "off" }.to_string();
+        Setting::ALL
+            .map(|setting| match setting {
+                Setting::Colormap => self.sys_metrics.colormap.to_string(),
+                Setting::Layout => self.layouts[self.layout].to_string(),
+                Setting::SampleRate => format!("{} Hz", self.sys_metrics.sample_rate_hz),
+                Setting::Metrics => self.cpu_selection().to_string(),
+                Setting::LightColor => menu::LIGHT_COLORS
+                    .iter()
+                    .find(|(_, color)| *color == self.light_uniform.color)
+                    .map_or("custom", |(name, _)| name)
+                    .to_string(),
+                Setting::LightRotation => format!("{}°/s", self.settings.render.light_rotation),
+                Setting::Msaa if self.sample_count == 1 => "off".to_string(),
+                Setting::Msaa => format!("{}x", self.sample_count),
+                Setting::Ground => self.ground.mode.to_string(),
+                Setting::Labels => on_off(self.labels.enabled),
+                Setting::Sparklines => self.sparklines.layout.to_string(),
+                Setting::Waterfall => on_off(self.sys_metrics.waterfall.enabled),
+            })
+            .to_vec()
+    }
+
+    // Steps a setting picked in the menu and saves it to the config file
+    fn change_setting(&mut self, setting: Setting, step: isize) {
+        let (key, value): (&str, toml_edit::Value) = match setting {
+            Setting::Colormap => {
+                let colormap = menu::step(&Colormap::ALL, &self.sys_metrics.colormap, step);
+                self.sys_metrics.colormap = colormap;
+                self.settings.render.colormap = Some(colormap);
+                ("render.colormap", colormap.to_string().into())
+            }
+            Setting::Layout => {
+                let layouts = self.layouts.len() as isize;
+                self.layout = (self.layout as isize + step).rem_euclid(layouts) as usize;
+                let layout = self.current_layout();
+                self.sys_metrics.set_layout(&layout);
+                let name = self.layouts[self.layout].to_string();
+                self.settings.layout.name = Some(name.clone());
+                ("layout.name", name.into())
+            }
+            Setting::SampleRate => {
+                let rates = &self.settings.sampling.rates;
+                let rate = menu::step(rates, &self.sys_metrics.sample_rate_hz, step);
+                self.sys_metrics.sample_rate_hz = rate;
+                self.settings.sampling.rate = Some(rate);
+                ("sampling.rate", Config::float(rate))
+            }
+            Setting::Metrics => {
+                let presets = menu::METRICS.map(|list| list.parse::<CpuSelection>().unwrap());
+                let current = self.cpu_selection();
+                let selection = menu::step(&presets, &current, step);
+                self.set_cpu_selection(selection.clone());
+                let list = selection.to_string();
+                self.settings.sampling.metrics = Some(selection);
+                ("sampling.metrics", list.into())
+            }
+            Setting::LightColor => {
+                let colors = menu::LIGHT_COLORS.map(|(_, color)| color);
+                let color = menu::step(&colors, &self.light_uniform.color, step);
+                self.light_uniform.color = color;
+                self.settings.render.light_color = color;
+                let color = color
+                    .map(Config::float)
+                    .into_iter()
+                    .collect::<toml_edit::Array>();
+                ("render.light_color", color.into())
+            }
+            Setting::LightRotation => {
+                let rotation = &self.settings.render.light_rotation;
+                let rotation = menu::step(&menu::LIGHT_ROTATIONS, rotation, step);
+                self.settings.render.light_rotation = rotation;
+                ("render.light_rotation", Config::float(rotation))
+            }
+            Setting::Msaa => {
+                let sample_count = menu::step(&self.sample_counts, &self.sample_count, step);
+                self.set_sample_count(sample_count);
+                self.settings.render.msaa = sample_count;
+                ("render.msaa", i64::from(sample_count).into())
+            }
+            Setting::Ground => {
+                self.ground.mode = menu::step(&GroundMode::ALL, &self.ground.mode, step);
+                self.settings.effects.ground = Some(self.ground.mode);
+                ("effects.ground", self.ground.mode.to_string().into())
+            }
+            Setting::Labels => {
+                self.labels.enabled = !self.labels.enabled;
+                self.settings.effects.labels = Some(self.labels.enabled);
+                ("effects.labels", self.labels.enabled.into())
+            }
+            Setting::Sparklines => {
+                let layout = menu::step(&SparklineLayout::ALL, &self.sparklines.layout, step);
+                self.sparklines.layout = layout;
+                self.settings.effects.sparklines = Some(layout);
+                ("effects.sparklines", layout.to_string().into())
+            }
+            Setting::Waterfall => {
+                self.toggle_waterfall();
+                let enabled = self.sys_metrics.waterfall.enabled;
+                self.settings.effects.waterfall = Some(enabled);
+                ("effects.waterfall", enabled.into())
+            }
         };
+        let Some(path) = self.config_watcher.path() else {
+            return;
+        };
+        if let Err(e) = Config::save_setting(path, key, value) {
+            self.report_config_error(e);
+        }
+    }
 
-        // We'll want to update our lights position, so we use COPY_DST
-        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Light VB"),
-            contents: bytemuck::cast_slice(&[light_uniform]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+    fn report_config_error(&mut self, error: anyhow::Error) {
+        log::error!("{:#}", error);
+        self.config_error = Some(format!("{:#}", error));
+    }
 
-        let depth_buffer = Self::depth_buffer(&device, &config);
-        let msaa_buffer = Self::msaa_buffer(&device, &config, 4);
+    // Rebuilds everything that depends on the number of MSAA samples
+    fn set_sample_count(&mut self, sample_count: u32) {
+        self.sample_count = sample_count;
+        self.render_pipeline = Self::render_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            self.config.format,
+            sample_count,
+        );
+        self.ground.set_sample_count(&self.device, sample_count);
+        self.depth_buffer = Self::depth_buffer(&self.device, &self.config, sample_count);
+        self.msaa_buffer = Self::msaa_buffer(&self.device, &self.config, sample_count);
+    }
 
-        let main_text =
-            text::Text::init_text(&device, &queue, surface_format, size.width, size.height);
+    // Command line options win over the view restored from the last session.
+    // Fails when a recording asked for can't be started.
+    fn apply_options(&mut self, options: &Options) -> anyhow::Result<()> {
+        if let Some(sample_rate_hz) = options.sample_rate_hz {
+            self.sys_metrics.sample_rate_hz = sample_rate_hz;
+        }
+        if let Some(colormap) = options.colormap {
+            self.sys_metrics.colormap = colormap;
+        }
+        if let Some(name) = &options.layout {
+            if let Some(index) = self
+                .layouts
+                .iter()
+                .position(|layout| layout.to_string() == *name)
+            {
+                self.layout = index;
+                let layout = self.current_layout();
+                self.sys_metrics.set_layout(&layout);
+            }
+        }
+        if options
+            .fullscreen
+            .is_some_and(|fullscreen| fullscreen != self.is_fullscreen)
+        {
+            self.toggle_fullscreen();
+        }
+        if options
+            .transparent
+            .is_some_and(|transparent| transparent != self.is_transparent)
+        {
+            self.toggle_transparent();
+        }
+        if let Some(path) = &options.record {
+            self.start_recording(path)?;
+        }
+        Ok(())
+    }
 
-        Self {
-            surface,
-            device,
-            queue,
-            config,
-            size,
-            render_pipeline,
+    fn toggle_recording(&mut self) {
+        if let Some(recorder) = self.sys_metrics.recorder.take() {
+            log::info!("stopped recording to {}", recorder.path().display());
+            return;
+        }
+        match self
+            .settings
+            .recording
+            .path
+            .clone()
+            .or_else(recording::default_path)
+        {
+            Some(path) => {
+                if let Err(e) = self.start_recording(&path) {
+                    log::error!("{:#}", e);
+                }
+            }
+            None => log::error!("nowhere to record to, set recording.path in the config"),
+        }
+    }
 
-            camera_controller,
-            sys_metrics,
-            window,
-            last_frame: Instant::now(),
-            light_uniform,
-            light_buffer,
-            light_bind_group,
-            depth_buffer,
-            msaa_buffer,
-            main_text,
-            is_fullscreen: false,
-            is_transparent: false,
+    // Multiplies the replay speed by `factor`, between 1/16 and 64 times
+    fn set_replay_speed(&mut self, factor: f32) {
+        if let Some(replay) = self.replay() {
+            let mut replay = replay.borrow_mut();
+            replay.speed = (replay.speed * factor).clamp(1.0 / 16.0, 64.0);
+        }
+    }
+
+    fn jump_replay(&mut self, seconds: f32) {
+        if let Some(replay) = self.replay() {
+            let mut replay = replay.borrow_mut();
+            let position = replay.position().as_secs_f32() + seconds;
+            replay.seek(Duration::from_secs_f32(position.max(0.0)));
         }
     }
 
-    fn depth_buffer(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> wgpu::Texture {
+    fn start_recording(&mut self, path: &Path) -> anyhow::Result<()> {
+        let recording = &self.settings.recording;
+        let recorder = Recorder::start(path, recording.max_bytes(), recording.max_files)?;
+        log::info!("recording to {}", path.display());
+        self.sys_metrics.recorder = Some(recorder);
+        Ok(())
+    }
+
+    fn depth_buffer(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) -> wgpu::Texture {
         let depth_buffer_size = wgpu::Extent3d {
             width: config.width.max(1),
             height: config.height.max(1),
@@ -264,7 +772,7 @@ impl<'a> State<'a> {
             label: Some("depth_texture"),
             size: depth_buffer_size,
             mip_level_count: 1,
-            sample_count: 4,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Depth32Float,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
@@ -314,9 +822,11 @@ impl<'a> State<'a> {
                 .camera_mut()
                 .resize(new_size.width as f32, new_size.height as f32);
 
-            self.depth_buffer = Self::depth_buffer(&self.device, &self.config);
-            self.msaa_buffer = Self::msaa_buffer(&self.device, &self.config, 4);
+            self.depth_buffer = Self::depth_buffer(&self.device, &self.config, self.sample_count);
+            self.msaa_buffer = Self::msaa_buffer(&self.device, &self.config, self.sample_count);
 
+            self.overlay
+                .resize(&self.queue, new_size.width, new_size.height);
             self.main_text
                 .resize(&self.queue, new_size.width, new_size.height);
             self.window.request_redraw();
@@ -345,70 +855,333 @@ impl<'a> State<'a> {
         self.surface.configure(&self.device, &self.config);
     }
 
+    // The waterfall lines the cores up in a row so their history strips don't
+    // run into each other
+    fn toggle_waterfall(&mut self) {
+        let waterfall = &mut self.sys_metrics.waterfall;
+        waterfall.enabled = !waterfall.enabled;
+        let layout = self.current_layout();
+        self.sys_metrics.set_layout(&layout);
+    }
+
+    fn current_layout(&self) -> Layout {
+        if self.sys_metrics.waterfall.enabled {
+            Layout::Row
+        } else {
+            self.layouts[self.layout].clone()
+        }
+    }
+
+    // Flat tiles seen straight from above, easier to read than bars when
+    // there are lots of cores
+    fn toggle_heatmap(&mut self) {
+        self.sys_metrics.flat = !self.sys_metrics.flat;
+        self.camera_controller.camera_mut().projection = if self.sys_metrics.flat {
+            Projection::Orthographic
+        } else {
+            Projection::Perspective
+        };
+        self.camera_controller.fit();
+    }
+
+    fn current_view(&self) -> View {
+        View {
+            camera: Some(self.camera_controller.pose()),
+            sample_rate_hz: Some(self.sys_metrics.sample_rate_hz),
+            // Not when the screensaver went fullscreen by itself
+            fullscreen: self.is_fullscreen && self.tour.mode() != Some(TourMode::Screensaver),
+            transparent: self.is_transparent,
+            heatmap: self.sys_metrics.flat,
+        }
+    }
+
+    fn apply_view(&mut self, view: &View, animate: bool) {
+        // The heatmap refits the camera, so switch it before posing
+        if view.heatmap != self.sys_metrics.flat {
+            self.toggle_heatmap();
+        }
+        if let Some(pose) = view.camera {
+            self.camera_controller.set_pose(pose, animate);
+        }
+        if let Some(sample_rate_hz) = view.sample_rate_hz.filter(|rate| *rate > 0.0) {
+            self.sys_metrics.sample_rate_hz = sample_rate_hz;
+        }
+        if view.fullscreen != self.is_fullscreen {
+            self.toggle_fullscreen();
+        }
+        if view.transparent != self.is_transparent {
+            self.toggle_transparent();
+        }
+    }
+
+    // Saved views are switched with v and added with shift+v
+    fn save_view(&mut self) {
+        let name = self.views.new_name();
+        self.views.saved.insert(name.clone(), self.current_view());
+        self.view = Some(name);
+    }
+
+    fn next_view(&mut self) {
+        let Some(name) = self.views.next_name(self.view.as_deref()) else {
+            return;
+        };
+        let view = self.views.saved[&name].clone();
+        self.apply_view(&view, true);
+        self.view = Some(name);
+    }
+
+    // Called on exit, remembers the current view for the next start
+    pub fn save_views(&mut self) {
+        self.views.last = self.current_view();
+        if let Err(e) = self.views.save() {
+            log::error!("{:#}", e);
+        }
+    }
+
+    fn stop_tour(&mut self) {
+        // The screensaver went fullscreen on its own, so it leaves it too
+        if self.tour.stop(&mut self.camera_controller) == Some(TourMode::Screensaver)
+            && self.is_fullscreen
+        {
+            self.toggle_fullscreen();
+        }
+    }
+
+    fn cycle_shape(&mut self) {
+        // Skip over meshes that fail to load
+        for _ in 0..self.shapes.len() {
+            self.shape = (self.shape + 1) % self.shapes.len();
+            match self.shapes[self.shape].model() {
+                Ok(model) => {
+                    self.sys_metrics.set_model(&self.device, &model);
+                    return;
+                }
+                Err(e) => log::error!("{:#}", e),
+            }
+        }
+    }
+
+    // The bar under the cursor, nearest to the camera
+    fn pick(&self) -> Option<usize> {
+        let ray = self.camera_controller.camera().ray(
+            self.cursor?,
+            self.size.width as f32,
+            self.size.height as f32,
+        )?;
+        self.sys_metrics
+            .bar_bounds()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, bar)| ray.intersect(bar).map(|t| (i, t)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    fn update_tooltip(&mut self) {
+        let screen = cgmath::Vector2::new(self.size.width as f32, self.size.height as f32);
+        let anchor = match (self.sys_metrics.hovered, self.cursor) {
+            (Some(cpu), Some(cursor)) => Some((cpu, cursor)),
+            // A pinned tooltip sticks to the top of its bar
+            _ => self.sys_metrics.selected.and_then(|cpu| {
+                let bar = self.sys_metrics.bar_bounds()[cpu];
+                let top = cgmath::Point3::new(
+                    (bar.min.x + bar.max.x) / 2.0,
+                    bar.max.y,
+                    (bar.min.z + bar.max.z) / 2.0,
+                );
+                let anchor = self
+                    .camera_controller
+                    .camera()
+                    .project(top, screen.x, screen.y)?;
+                Some((cpu, anchor))
+            }),
+        };
+        match anchor {
+            Some((cpu, anchor)) => self.tooltip.show(
+                &mut self.main_text.font_system,
+                &mut self.overlay,
+                &self.sys_metrics,
+                cpu,
+                anchor,
+                screen,
+            ),
+            None => self.tooltip.hide(),
+        }
+    }
+
     pub fn input(&mut self, event: &WindowEvent) -> bool {
+        let touring = self.tour.mode().is_some();
+        if self.tour.input(event) {
+            self.stop_tour();
+        }
+        if self.menu.enabled {
+            match self.menu.input(event) {
+                MenuInput::Change(setting, step) => {
+                    self.change_setting(setting, step);
+                    return true;
+                }
+                MenuInput::Handled => return true,
+                MenuInput::Ignored => {}
+            }
+        }
+        if self.scrubber.input(event, &mut self.sys_metrics) {
+            return true;
+        }
         self.camera_controller.process_events(event);
         match event {
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        logical_key: Key::Named(NamedKey::Space),
-                        state: winit::event::ElementState::Pressed,
-                        ..
-                    },
-                ..
-            } => {
-                dbg!("space pressed");
-                true
-            }
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        state: ElementState::Pressed,
-                        logical_key: Key::Character(c),
-                        ..
-                    },
-                ..
-            } => match c.to_lowercase().as_str() {
-                "f" => {
-                    self.toggle_fullscreen();
-                    true
-                }
-                "t" => {
-                    self.toggle_transparent();
-                    false
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = Some(cgmath::Vector2::new(position.x as f32, position.y as f32));
+                false
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+                false
+            }
+            _ => {
+                let actions = self.bindings.actions(event, self.modifiers);
+                let mut handled = false;
+                for (action, pressed) in actions {
+                    // Any input stops the tour, so that is all its own key does
+                    // while touring
+                    if action == Action::StartTour && touring {
+                        handled = true;
+                        continue;
+                    }
+                    handled |= self.action(action, pressed);
                 }
-                "r" => {
-                    // cycle through the available sample rates
-                    let sample_rates = [0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0];
-                    let current_rate = self.sys_metrics.sample_rate_hz;
-                    let new_rate = sample_rates
-                        .iter()
-                        .find(|&&r| r > current_rate)
-                        .unwrap_or(&sample_rates[0]);
-                    self.sys_metrics.sample_rate_hz = *new_rate;
-                    true
+                handled
+            }
+        }
+    }
+
+    fn action(&mut self, action: Action, pressed: bool) -> bool {
+        if self.camera_controller.action(action, pressed) {
+            return true;
+        }
+        if !pressed {
+            return false;
+        }
+        match action {
+            Action::Quit if self.help.enabled || self.menu.enabled => {
+                self.help.enabled = false;
+                self.menu.enabled = false;
+            }
+            Action::Quit => self.quit = true,
+            Action::ToggleHelp => self.help.enabled = !self.help.enabled,
+            Action::ToggleMenu => self.menu.enabled = !self.menu.enabled,
+            Action::ToggleFullscreen => self.toggle_fullscreen(),
+            Action::ToggleTransparent => self.toggle_transparent(),
+            Action::CycleShape => self.cycle_shape(),
+            Action::CycleLayout => {
+                self.layout = (self.layout + 1) % self.layouts.len();
+                let layout = self.current_layout();
+                self.sys_metrics.set_layout(&layout);
+            }
+            Action::ToggleWaterfall => self.toggle_waterfall(),
+            Action::CycleSparklines => self.sparklines.layout = self.sparklines.layout.next(),
+            Action::ToggleLabels => self.labels.enabled = !self.labels.enabled,
+            Action::ToggleHeatmap => self.toggle_heatmap(),
+            Action::CycleGround => self.ground.mode = self.ground.mode.next(),
+            Action::NextView => self.next_view(),
+            Action::SaveView => self.save_view(),
+            Action::StartTour => self.tour.start(
+                TourMode::Manual,
+                &mut self.camera_controller,
+                &self.sys_metrics,
+            ),
+            // Sampling carries on while paused, only the display stops
+            Action::TogglePause if self.sys_metrics.is_paused() => self.sys_metrics.resume(),
+            Action::TogglePause => self.sys_metrics.pause(),
+            Action::StepBack => self.sys_metrics.scrub(-1),
+            Action::StepForward => self.sys_metrics.scrub(1),
+            Action::GoLive => self.sys_metrics.resume(),
+            Action::ToggleRecording => self.toggle_recording(),
+            Action::ReplaySlower => self.set_replay_speed(0.5),
+            Action::ReplayFaster => self.set_replay_speed(2.0),
+            Action::ReplayBack => self.jump_replay(-10.0),
+            Action::ReplayForward => self.jump_replay(10.0),
+            Action::CycleSampleRate => {
+                // cycle through the available sample rates
+                let sample_rates = &self.settings.sampling.rates;
+                let current_rate = self.sys_metrics.sample_rate_hz;
+                let new_rate = sample_rates
+                    .iter()
+                    .find(|&&r| r > current_rate)
+                    .unwrap_or(&sample_rates[0]);
+                self.sys_metrics.sample_rate_hz = *new_rate;
+            }
+            // Clicking a bar pins it, clicking it again unpins it. Clicks
+            // that miss drag the window.
+            Action::Pin => match self.sys_metrics.hovered {
+                Some(cpu) => {
+                    self.sys_metrics.selected = if self.sys_metrics.selected == Some(cpu) {
+                        None
+                    } else {
+                        Some(cpu)
+                    };
                 }
-                _ => false,
+                None => self.drag_window(),
             },
+            Action::DragWindow => self.drag_window(),
+            _ => return false,
+        }
+        true
+    }
 
-            _ => false,
+    fn drag_window(&self) {
+        if let Err(e) = self.window.drag_window() {
+            log::error!("failed to drag the window: {}", e);
         }
     }
 
+    // True once the quit action was used
+    pub fn quit_requested(&self) -> bool {
+        self.quit
+    }
+
     pub fn update(&mut self) {
         let now = Instant::now();
         let dt = now - self.last_frame;
         self.last_frame = now;
 
+        match self.config_watcher.poll() {
+            Some(Ok(config)) => self.apply_config(config),
+            Some(Err(e)) => self.report_config_error(e),
+            None => {}
+        }
+
+        let (min, max) = self.sys_metrics.bounds();
+        self.camera_controller.set_bounds(min, max);
+        if self.tour.idle() {
+            self.tour.start(
+                TourMode::Screensaver,
+                &mut self.camera_controller,
+                &self.sys_metrics,
+            );
+            if !self.is_fullscreen {
+                self.toggle_fullscreen();
+            }
+        }
+        self.tour
+            .update(&mut self.camera_controller, &self.sys_metrics);
         self.camera_controller.update(dt, &mut self.queue);
-        self.sys_metrics.update(&self.queue);
+        self.sys_metrics.hovered = self.pick();
+        self.sys_metrics.update(dt, &self.queue);
+        let (min, max) = self.sys_metrics.bounds();
+        self.ground
+            .set_bounds(&self.queue, min, max, self.sys_metrics.spacing);
 
         // Update the light
         let old_position: cgmath::Vector3<_> = self.light_uniform.position.into();
-        self.light_uniform.position =
-            (cgmath::Quaternion::from_axis_angle((0.0, 1.0, 0.0).into(), cgmath::Deg(1.0))
-                * old_position)
-                .into();
+        self.light_uniform.position = (cgmath::Quaternion::from_axis_angle(
+            (0.0, 1.0, 0.0).into(),
+            cgmath::Deg(self.settings.render.light_rotation * dt.as_secs_f32()),
+        ) * old_position)
+            .into();
 
         self.queue.write_buffer(
             &self.light_buffer,
@@ -416,14 +1189,108 @@ impl<'a> State<'a> {
             bytemuck::cast_slice(&[self.light_uniform]),
         );
 
-        self.main_text.set_text(
-            &[
-                "lolitop v0.1",
-                format!("FPS: {:.2}", 1.0 / dt.as_secs_f64()).as_str(),
-                format!("Sample rate: {}hz", self.sys_metrics.sample_rate_hz).as_str(),
-            ]
-            .join("\n"),
+        self.overlay.clear();
+        self.sparklines.draw(
+            &mut self.overlay,
+            &self.sys_metrics,
+            self.size.width as f32,
+            self.size.height as f32,
         );
+
+        // All text is drawn after all panels, so text of the panels below the
+        // help would show through it. They are left out while it is open.
+        if !self.help.enabled {
+            self.labels.update(
+                &mut self.main_text.font_system,
+                self.camera_controller.camera(),
+                &self.sys_metrics,
+                self.size.width as f32,
+                self.size.height as f32,
+            );
+            // Below the status text in the top left corner, where the menu
+            // goes too, which hides it
+            let top = self.main_text.height() + 10.0;
+            if !self.menu.enabled {
+                self.drilldown.update(
+                    &mut self.main_text.font_system,
+                    &mut self.overlay,
+                    &self.sys_metrics,
+                    top,
+                );
+            }
+            self.update_tooltip();
+            self.scrubber.update(
+                &mut self.main_text.font_system,
+                &mut self.overlay,
+                &self.sys_metrics,
+                self.size.width as f32,
+                self.size.height as f32,
+            );
+            if self.menu.enabled {
+                let values = self.setting_values();
+                let hint = match self.bindings.keys(Action::ToggleMenu).next() {
+                    Some(key) => format!("Arrow keys or mouse to change, {} to close", key),
+                    None => "Arrow keys or mouse to change".to_string(),
+                };
+                self.menu.update(
+                    &mut self.main_text.font_system,
+                    &mut self.overlay,
+                    &values,
+                    &hint,
+                    top,
+                );
+            }
+        }
+        // Last, so it covers the sparklines
+        self.help.update(
+            &mut self.main_text.font_system,
+            &mut self.overlay,
+            &self.bindings,
+            self.size.width as f32,
+            self.size.height as f32,
+        );
+
+        let mut status = vec![
+            "lolitop v0.1".to_string(),
+            format!("FPS: {:.2}", 1.0 / dt.as_secs_f64()),
+            format!("Sample rate: {}hz", self.sys_metrics.sample_rate_hz),
+        ];
+        if let Some(key) = self.bindings.keys(Action::ToggleHelp).next() {
+            status.push(format!("Help: {}", key));
+        }
+        if self.sys_metrics.is_paused() {
+            status.push("Paused".to_string());
+        }
+        if let Some(replay) = self.replay() {
+            let replay = replay.borrow();
+            let minutes = |time: Duration| {
+                let seconds = time.as_secs();
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            };
+            status.push(format!(
+                "Replay: {} / {} at {}x",
+                minutes(replay.position()),
+                minutes(replay.duration()),
+                replay.speed
+            ));
+        }
+        if let Some(scenario) = self.options.synthetic {
+            status.push(format!(
+                "Synthetic: {}, {} cores",
+                scenario, self.options.cores
+            ));
+        }
+        if let Some(recorder) = &self.sys_metrics.recorder {
+            let name = recorder.path().file_name().unwrap_or_default();
+            status.push(format!("Recording: {}", name.to_string_lossy()));
+        }
+        if let Some(view) = &self.view {
+            status.push(format!("View: {}", view));
+        }
+        if let Some(error) = &self.config_error {
+            status.push(format!("Config: {}", error));
+        }
+        self.main_text.set_text(&status.join("\n"));
         self.window.request_redraw();
     }
 
@@ -439,11 +1306,17 @@ impl<'a> State<'a> {
                 label: Some("Render Encoder"),
             });
         {
+            // Without multisampling there is nothing to resolve
+            let (target, resolve_target) = if self.sample_count > 1 {
+                (&self.msaa_buffer, Some(&view))
+            } else {
+                (&view, None)
+            };
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &self.msaa_buffer,
-                    resolve_target: Some(&view),
+                    view: target,
+                    resolve_target,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
                             r: 0.0,
@@ -474,9 +1347,30 @@ impl<'a> State<'a> {
                 &self.light_bind_group,
                 &self.camera_controller,
             );
+            // The levels would cover the heatmap tiles
+            if !self.sys_metrics.flat {
+                self.ground
+                    .render(&mut render_pass, &self.camera_controller);
+            }
         }
-        self.main_text
+        self.overlay
             .render(&self.device, &view, &mut encoder, &self.queue);
+        // Only the help while it is open, see `update`
+        let panels = (!self.help.enabled).then(|| {
+            self.labels
+                .text_areas()
+                .chain(self.drilldown.text_area().filter(|_| !self.menu.enabled))
+                .chain(self.tooltip.text_area())
+                .chain(self.scrubber.text_areas())
+                .chain(self.menu.text_areas())
+        });
+        self.main_text.render(
+            &self.device,
+            &view,
+            &mut encoder,
+            &self.queue,
+            panels.into_iter().flatten().chain(self.help.text_areas()),
+        );
 
         self.queue.submit(iter::once(encoder.finish()));
         output.present();
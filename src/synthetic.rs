@@ -0,0 +1,239 @@
+use std::f32::consts::TAU;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::source::{CounterSource, Row};
+
+// Ticks each core adds up to per sample, like jiffies in /proc/stat
+const TICKS: u64 = 1000;
+// Length of the sine period and the fork bomb ramp, in samples
+const PERIOD: u64 = 60;
+
+// Made up load for demos and for looking at big machines from a small one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scenario {
+    // Waves running across the cores
+    Sine,
+    // Quiet cores with the odd short burst
+    Spikes,
+    // The first core busy all the time, the rest idling
+    Pegged,
+    // One more core pegged every few samples until all are, then again
+    ForkBomb,
+    // One thread of each sibling pair busy and the other idle
+    Smt,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 5] = [
+        Scenario::Sine,
+        Scenario::Spikes,
+        Scenario::Pegged,
+        Scenario::ForkBomb,
+        Scenario::Smt,
+    ];
+}
+
+impl fmt::Display for Scenario {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Scenario::Sine => "sine",
+            Scenario::Spikes => "spikes",
+            Scenario::Pegged => "pegged",
+            Scenario::ForkBomb => "fork-bomb",
+            Scenario::Smt => "smt",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Scenario {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        Scenario::ALL
+            .into_iter()
+            .find(|scenario| scenario.to_string() == name)
+            .ok_or_else(|| {
+                let names = Scenario::ALL.map(|scenario| scenario.to_string());
+                anyhow::anyhow!(
+                    "unknown scenario '{}', expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+// xorshift64*, plenty for noise and the same numbers on every run
+struct Random(u64);
+
+impl Random {
+    // Between 0 and 1
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+// Generates the counters of `cores` cores playing out a scenario. Time moves
+// one step per sample, so a run is the same at any sample rate.
+pub struct Synthetic {
+    pub scenario: Scenario,
+    step: u64,
+    random: Random,
+    // Busy and idle ticks so far, per core
+    counters: Vec<(u64, u64)>,
+    // What is left of the current spike, per core
+    spikes: Vec<f32>,
+}
+
+impl Synthetic {
+    pub fn new(scenario: Scenario, cores: usize) -> Self {
+        Synthetic {
+            scenario,
+            step: 0,
+            random: Random(0x9e37_79b9_7f4a_7c15),
+            counters: vec![(0, 0); cores],
+            spikes: vec![0.0; cores],
+        }
+    }
+
+    // Usage of `core` for the current step, between 0 and 1
+    fn usage(&mut self, core: usize) -> f32 {
+        let cores = self.counters.len();
+        let noise = 0.05 * self.random.next();
+        let usage = match self.scenario {
+            Scenario::Sine => {
+                let phase = self.step as f32 / PERIOD as f32 + core as f32 / cores as f32;
+                0.5 + 0.45 * (phase * TAU).sin()
+            }
+            Scenario::Spikes => {
+                if self.random.next() < 0.01 {
+                    self.spikes[core] = 0.8 + 0.2 * self.random.next();
+                }
+                let spike = self.spikes[core];
+                self.spikes[core] *= 0.7;
+                0.05 + spike
+            }
+            Scenario::Pegged if core == 0 => 1.0,
+            Scenario::Pegged => 0.05,
+            Scenario::ForkBomb => {
+                let forked = (self.step % PERIOD) as usize * cores / PERIOD as usize;
+                if core <= forked {
+                    1.0
+                } else {
+                    0.05
+                }
+            }
+            // Siblings are numbered next to each other
+            Scenario::Smt if core.is_multiple_of(2) => 0.9,
+            Scenario::Smt => 0.1,
+        };
+        (usage + noise).clamp(0.0, 1.0)
+    }
+}
+
+impl CounterSource for Synthetic {
+    fn sample(&mut self) -> anyhow::Result<Vec<Row>> {
+        for core in 0..self.counters.len() {
+            let busy = (self.usage(core) * TICKS as f32) as u64;
+            let (total_busy, total_idle) = &mut self.counters[core];
+            *total_busy += busy;
+            *total_idle += TICKS - busy;
+        }
+        self.step += 1;
+
+        // All busy time counts as user time
+        let row = |busy: u64, idle: u64| vec![busy, 0, 0, idle, 0, 0, 0, 0, 0, 0];
+        let (busy, idle) = self
+            .counters
+            .iter()
+            .fold((0, 0), |(busy, idle), (b, i)| (busy + b, idle + i));
+        let mut rows = vec![("cpu".to_string(), row(busy, idle))];
+        for (core, (busy, idle)) in self.counters.iter().enumerate() {
+            rows.push((format!("cpu{}", core), row(*busy, *idle)));
+        }
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli;
+
+    // Usage of every core over the next sample, between 0 and 1
+    fn step(synthetic: &mut Synthetic, before: &mut Vec<Row>) -> Vec<f32> {
+        let rows = synthetic.sample().unwrap();
+        let usage = rows
+            .iter()
+            .zip(before.iter())
+            .skip(1)
+            .map(|((_, now), (_, then))| {
+                let busy = now[0] - then[0];
+                let idle = now[3] - then[3];
+                busy as f32 / (busy + idle) as f32
+            })
+            .collect();
+        *before = rows;
+        usage
+    }
+
+    fn run(scenario: Scenario, cores: usize, steps: usize) -> Vec<Vec<f32>> {
+        let mut synthetic = Synthetic::new(scenario, cores);
+        let mut before = synthetic.sample().unwrap();
+        (0..steps)
+            .map(|_| step(&mut synthetic, &mut before))
+            .collect()
+    }
+
+    #[test]
+    fn runs_are_the_same() {
+        for scenario in Scenario::ALL {
+            let mut a = Synthetic::new(scenario, 8);
+            let mut b = Synthetic::new(scenario, 8);
+            for _ in 0..100 {
+                assert_eq!(a.sample().unwrap(), b.sample().unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn pegged_keeps_the_first_core_busy() {
+        for usage in run(Scenario::Pegged, 8, 50) {
+            assert!(usage[0] >= 0.95, "cpu0 at {}", usage[0]);
+            for other in &usage[1..] {
+                assert!(*other <= 0.15, "another core at {}", other);
+            }
+        }
+    }
+
+    #[test]
+    fn fork_bomb_takes_over_core_by_core() {
+        let cores = 8;
+        let busy = run(Scenario::ForkBomb, cores, PERIOD as usize - 1)
+            .iter()
+            .map(|usage| usage.iter().filter(|usage| **usage > 0.9).count())
+            .collect::<Vec<_>>();
+        assert_eq!(busy[0], 1);
+        for pair in busy.windows(2) {
+            assert!(pair[1] == pair[0] || pair[1] == pair[0] + 1, "{:?}", busy);
+        }
+        assert_eq!(*busy.last().unwrap(), cores);
+    }
+
+    #[test]
+    fn cores_option_sets_the_rows() {
+        let args = ["--synthetic", "sine", "--cores", "256"].map(String::from);
+        let options = cli::parse(args).unwrap().unwrap();
+        let mut synthetic = Synthetic::new(options.synthetic.unwrap(), options.cores);
+        let rows = synthetic.sample().unwrap();
+        // The total and one per core
+        assert_eq!(rows.len(), 257);
+        assert_eq!(rows[0].0, "cpu");
+        assert_eq!(rows[256].0, "cpu255");
+    }
+}
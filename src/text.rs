@@ -8,6 +8,8 @@ use wgpu::TextureFormat;
 
 pub struct Text {
     pub text_buffer: Buffer,
+    // Applied when drawing the main text only
+    pub scale: f32,
     pub font_system: FontSystem,
     pub swash_cache: SwashCache,
     pub cache: Cache,
@@ -49,6 +51,7 @@ impl Text {
         text_buffer.shape_until_scroll(&mut font_system, false);
         Self {
             text_buffer,
+            scale: 2.0,
             font_system,
             swash_cache,
             cache,
@@ -69,6 +72,22 @@ impl Text {
             .shape_until_scroll(&mut self.font_system, false);
     }
 
+    pub fn set_font_size(&mut self, size: f32) {
+        self.text_buffer
+            .set_metrics(&mut self.font_system, Metrics::new(size, size));
+        self.text_buffer
+            .shape_until_scroll(&mut self.font_system, false);
+    }
+
+    // Height of the main text on screen, for placing things below it
+    pub fn height(&self) -> f32 {
+        self.text_buffer
+            .layout_runs()
+            .map(|run| run.line_top + run.line_height)
+            .fold(0.0, f32::max)
+            * self.scale
+    }
+
     pub fn resize(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
         self.text_buffer.set_size(
             &mut self.font_system,
@@ -84,13 +103,33 @@ impl Text {
         );
     }
 
-    pub fn render(
+    // Draws the main text along with any other text areas shaped with our
+    // font system, such as the labels
+    pub fn render<'a>(
         &mut self,
         device: &wgpu::Device,
         view: &wgpu::TextureView,
         encoder: &mut wgpu::CommandEncoder,
         queue: &wgpu::Queue,
+        areas: impl IntoIterator<Item = TextArea<'a>>,
     ) {
+        let mut text_areas = vec![TextArea {
+            buffer: &self.text_buffer,
+            left: 0.0,
+            top: 0.0,
+            scale: self.scale,
+            bounds: TextBounds {
+                left: 0,
+                top: 0,
+                right: 2000,
+                bottom: 2000,
+            },
+            default_color: Color::rgba(255, 255, 255, 255),
+            custom_glyphs: &[],
+        }];
+        for area in areas {
+            text_areas.push(area);
+        }
         self.text_renderer
             .prepare(
                 device,
@@ -98,20 +137,7 @@ impl Text {
                 &mut self.font_system,
                 &mut self.atlas,
                 &self.viewport,
-                [TextArea {
-                    buffer: &self.text_buffer,
-                    left: 0.0,
-                    top: 0.0,
-                    scale: 2.0,
-                    bounds: TextBounds {
-                        left: 0,
-                        top: 0,
-                        right: 2000,
-                        bottom: 2000,
-                    },
-                    default_color: Color::rgba(255, 255, 255, 255),
-                    custom_glyphs: &[],
-                }],
+                text_areas,
                 &mut self.swash_cache,
             )
             .unwrap();
@@ -135,3 +161,24 @@ impl Text {
             .unwrap();
     }
 }
+
+// Unwrapped panel text in our font
+pub fn shape(font_system: &mut FontSystem, text: &str, font_size: f32) -> Buffer {
+    let mut buffer = Buffer::new(font_system, Metrics::new(font_size, font_size * 1.3));
+    buffer.set_size(font_system, None, None);
+    buffer.set_text(
+        font_system,
+        text,
+        &Attrs::new().family(Family::Name("Inter")),
+        Shaping::Advanced,
+    );
+    buffer.shape_until_scroll(font_system, false);
+    buffer
+}
+
+// Width of the widest line and height of all of them
+pub fn size(buffer: &Buffer) -> (f32, f32) {
+    buffer.layout_runs().fold((0.0f32, 0.0f32), |(w, h), run| {
+        (w.max(run.line_w), h.max(run.line_top + run.line_height))
+    })
+}
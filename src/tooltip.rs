@@ -0,0 +1,125 @@
+use cgmath::Vector2;
+use glyphon::{Buffer, Color, FontSystem, TextArea, TextBounds};
+
+use crate::metrics::SysMetrics;
+use crate::overlay::{Overlay, PANEL};
+use crate::source::Channel;
+use crate::sparklines;
+use crate::text::{shape, size};
+
+const FONT_SIZE: f32 = 14.0;
+const PADDING: f32 = 8.0;
+const GRAPH_HEIGHT: f32 = 40.0;
+// Seconds of history in the graph
+const GRAPH_SECONDS: f32 = 30.0;
+
+// Details of a single instance next to the cursor, or next to its bar when the
+// selection is pinned
+pub struct Tooltip {
+    buffer: Option<Buffer>,
+    visible: bool,
+    left: f32,
+    top: f32,
+}
+
+impl Default for Tooltip {
+    fn default() -> Self {
+        Tooltip {
+            buffer: None,
+            visible: false,
+            left: 0.0,
+            top: 0.0,
+        }
+    }
+}
+
+impl Tooltip {
+    pub fn hide(&mut self) {
+        self.visible = false;
+    }
+
+    pub fn show(
+        &mut self,
+        font_system: &mut FontSystem,
+        overlay: &mut Overlay,
+        sys_metrics: &SysMetrics,
+        index: usize,
+        anchor: Vector2<f32>,
+        screen: Vector2<f32>,
+    ) {
+        let (Some((source, instance)), Some(id)) = (
+            sys_metrics.locate(index),
+            sys_metrics.instance_ids().nth(index),
+        ) else {
+            self.hide();
+            return;
+        };
+        let value = sys_metrics.values.get(index).copied().unwrap_or(0.0);
+
+        let mut lines = vec![format!("{}  {:.0}%", id, value * 100.0)];
+        lines.extend(source.details(instance));
+        // The first channel is the one in the heading
+        let channels = source
+            .channels()
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter_map(|(channel, Channel { name, unit, .. })| {
+                let value = source.value(instance, channel)?;
+                Some(format!("{} {:.1} {}", name, value, unit))
+            })
+            .collect::<Vec<_>>();
+        if !channels.is_empty() {
+            lines.push(channels.join("  "));
+        }
+
+        let buffer = self
+            .buffer
+            .insert(shape(font_system, &lines.join("\n"), FONT_SIZE));
+        let (text_width, text_height) = size(buffer);
+
+        let width = text_width.max(200.0) + 2.0 * PADDING;
+        let height = text_height + GRAPH_HEIGHT + 3.0 * PADDING;
+
+        // Below and to the right of the anchor, flipped to stay on screen
+        let mut left = anchor.x + 16.0;
+        let mut top = anchor.y + 16.0;
+        if left + width > screen.x {
+            left = (anchor.x - 16.0 - width).max(0.0);
+        }
+        if top + height > screen.y {
+            top = (anchor.y - 16.0 - height).max(0.0);
+        }
+
+        overlay.rect(left, top, width, height, PANEL);
+        let samples = (GRAPH_SECONDS * sys_metrics.sample_rate_hz).ceil() as usize;
+        sparklines::graph(
+            overlay,
+            sys_metrics.history(index),
+            samples,
+            left + PADDING,
+            top + text_height + 2.0 * PADDING,
+            width - 2.0 * PADDING,
+            GRAPH_HEIGHT,
+        );
+
+        self.left = left + PADDING;
+        self.top = top + PADDING;
+        self.visible = true;
+    }
+
+    pub fn text_area(&self) -> Option<TextArea<'_>> {
+        if !self.visible {
+            return None;
+        }
+        Some(TextArea {
+            buffer: self.buffer.as_ref()?,
+            left: self.left,
+            top: self.top,
+            scale: 1.0,
+            bounds: TextBounds::default(),
+            default_color: Color::rgba(255, 255, 255, 255),
+            custom_glyphs: &[],
+        })
+    }
+}
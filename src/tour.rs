@@ -0,0 +1,175 @@
+use std::time::{Duration, Instant};
+
+use cgmath::{Deg, EuclideanSpace, Point3};
+use winit::event::{ElementState, WindowEvent};
+
+use crate::camera::{CameraController, Preset};
+use crate::metrics::SysMetrics;
+
+// Distance to the hottest core when zoomed in on it
+const CLOSE_UP_DISTANCE: f32 = 8.0;
+// Going fullscreen moves the cursor relative to the window, don't let that
+// end the tour right away
+const MOTION_GRACE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shot {
+    // The whole scene from above, slowly turning
+    Overview,
+    // Close to the busiest core, following it around
+    Hottest,
+    // Low along the floor
+    Sweep,
+}
+
+const SCRIPT: [(Shot, Duration); 5] = [
+    (Shot::Overview, Duration::from_secs(15)),
+    (Shot::Hottest, Duration::from_secs(10)),
+    (Shot::Overview, Duration::from_secs(10)),
+    (Shot::Sweep, Duration::from_secs(12)),
+    (Shot::Hottest, Duration::from_secs(10)),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TourMode {
+    // Started with the tour key
+    Manual,
+    // Started after the input has been idle, runs fullscreen
+    Screensaver,
+}
+
+// Drives the camera through the shots in SCRIPT on its own, over and over,
+// until there is user input
+pub struct Tour {
+    mode: Option<TourMode>,
+    // Start the screensaver after this long without input, never when None
+    pub idle_timeout: Option<Duration>,
+    last_input: Instant,
+    started: Instant,
+    shot: usize,
+    shot_start: Instant,
+    // Core followed during a Hottest shot, picked when the shot starts
+    core: Option<usize>,
+}
+
+impl Default for Tour {
+    fn default() -> Self {
+        Tour {
+            mode: None,
+            idle_timeout: None,
+            last_input: Instant::now(),
+            started: Instant::now(),
+            shot: 0,
+            shot_start: Instant::now(),
+            core: None,
+        }
+    }
+}
+
+impl Tour {
+    pub fn mode(&self) -> Option<TourMode> {
+        self.mode
+    }
+
+    pub fn start(
+        &mut self,
+        mode: TourMode,
+        camera_controller: &mut CameraController,
+        sys_metrics: &SysMetrics,
+    ) {
+        self.mode = Some(mode);
+        self.started = Instant::now();
+        self.shot = SCRIPT.len() - 1;
+        self.next_shot(camera_controller, sys_metrics);
+    }
+
+    // Stops the tour and hands back the mode it was running in
+    pub fn stop(&mut self, camera_controller: &mut CameraController) -> Option<TourMode> {
+        let mode = self.mode.take();
+        if mode.is_some() {
+            camera_controller.set_preset(Preset::Isometric);
+        }
+        mode
+    }
+
+    // Restarts the idle timer on user input. True when the input should end
+    // a running tour.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        let user_input = match event {
+            WindowEvent::KeyboardInput { event, .. } => event.state == ElementState::Pressed,
+            WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::Touch(_)
+            | WindowEvent::PinchGesture { .. }
+            | WindowEvent::RotationGesture { .. } => true,
+            WindowEvent::CursorMoved { .. } => {
+                self.mode.is_none() || self.started.elapsed() > MOTION_GRACE
+            }
+            _ => false,
+        };
+        if user_input {
+            self.last_input = Instant::now();
+        }
+        user_input && self.mode.is_some()
+    }
+
+    // True when the screensaver should start
+    pub fn idle(&self) -> bool {
+        self.mode.is_none()
+            && self
+                .idle_timeout
+                .is_some_and(|timeout| self.last_input.elapsed() >= timeout)
+    }
+
+    pub fn update(&mut self, camera_controller: &mut CameraController, sys_metrics: &SysMetrics) {
+        if self.mode.is_none() {
+            return;
+        }
+        if self.shot_start.elapsed() >= SCRIPT[self.shot].1 {
+            self.next_shot(camera_controller, sys_metrics);
+        }
+
+        // Follow the core while the layout moves it around
+        if SCRIPT[self.shot].0 == Shot::Hottest {
+            let target = self.core.and_then(|core| {
+                let instance = sys_metrics.instances.get(core)?;
+                let height = sys_metrics
+                    .visuals
+                    .get(core)
+                    .map_or(0.0, |visuals| visuals.height);
+                let mut top = Point3::from_vec(instance.position());
+                top.y = sys_metrics.bar_height(height) / 2.0;
+                Some((top, CLOSE_UP_DISTANCE))
+            });
+            camera_controller.focus(target);
+        }
+    }
+
+    fn next_shot(&mut self, camera_controller: &mut CameraController, sys_metrics: &SysMetrics) {
+        self.shot = (self.shot + 1) % SCRIPT.len();
+        self.shot_start = Instant::now();
+        match SCRIPT[self.shot].0 {
+            Shot::Overview => {
+                camera_controller.orbit(Deg(45.0), 6.0);
+                camera_controller.focus(None);
+            }
+            Shot::Hottest => {
+                self.core = hottest(sys_metrics);
+                camera_controller.orbit(Deg(25.0), 10.0);
+            }
+            Shot::Sweep => {
+                camera_controller.orbit(Deg(8.0), 15.0);
+                camera_controller.focus(None);
+            }
+        }
+    }
+}
+
+fn hottest(sys_metrics: &SysMetrics) -> Option<usize> {
+    sys_metrics
+        .values
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(core, _)| core)
+}
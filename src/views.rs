@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::CameraPose;
+use crate::paths;
+
+// Everything about how the scene is looked at that survives a restart
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct View {
+    pub camera: Option<CameraPose>,
+    pub sample_rate_hz: Option<f32>,
+    pub fullscreen: bool,
+    pub transparent: bool,
+    pub heatmap: bool,
+}
+
+// The view at the last exit plus the views saved by name, kept in
+// $XDG_STATE_HOME/lolitop/state.toml. Names can be edited in the file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Views {
+    pub last: View,
+    pub saved: BTreeMap<String, View>,
+}
+
+impl Views {
+    fn path() -> Option<PathBuf> {
+        paths::state_dir().map(|dir| dir.join("state.toml"))
+    }
+
+    // A missing file is a first start, anything else unreadable is logged and
+    // replaced on the next save
+    pub fn load() -> Views {
+        let Some(path) = Views::path() else {
+            return Views::default();
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Views::default(),
+            Err(e) => {
+                log::error!("failed to read {}: {}", path.display(), e);
+                return Views::default();
+            }
+        };
+        toml::from_str(&contents)
+            .with_context(|| format!("invalid state file {}", path.display()))
+            .unwrap_or_else(|e| {
+                log::error!("{:#}", e);
+                Views::default()
+            })
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let Some(path) = Views::path() else {
+            return Ok(());
+        };
+        let dir = path.parent().unwrap();
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        // Write next to it and rename so a crash never leaves half a file
+        let temporary = path.with_extension("toml.tmp");
+        fs::write(&temporary, toml::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write {}", temporary.display()))?;
+        fs::rename(&temporary, &path)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    // First free name of the form `view N`
+    pub fn new_name(&self) -> String {
+        (1..)
+            .map(|n| format!("view {}", n))
+            .find(|name| !self.saved.contains_key(name))
+            .unwrap()
+    }
+
+    // The saved view after `current`, in name order, wrapping around
+    pub fn next_name(&self, current: Option<&str>) -> Option<String> {
+        let mut names = self.saved.keys();
+        let next = match current {
+            Some(current) => names.find(|name| name.as_str() > current),
+            None => None,
+        };
+        next.or_else(|| self.saved.keys().next()).cloned()
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+
+use wgpu::util::DeviceExt;
+
+use crate::encoding::Visuals;
+use crate::metrics::{Instance, InstanceRaw};
+
+// Number of past samples drawn behind each core
+pub const DEPTH: usize = 40;
+
+// Rows of history bars extending back from every core along -z, the newest
+// sample right behind the live bar. Rows scroll back as samples come in, half
+// the spacing of the bars apart.
+pub struct Waterfall {
+    pub enabled: bool,
+    ncpus: usize,
+    instance_buffer: wgpu::Buffer,
+    value_buffer: wgpu::Buffer,
+}
+
+impl Waterfall {
+    pub fn new(device: &wgpu::Device, ncpus: usize) -> Self {
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Waterfall Instance Buffer"),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            size: (ncpus * DEPTH * std::mem::size_of::<InstanceRaw>()) as u64,
+            mapped_at_creation: false,
+        });
+        let value_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Waterfall Values"),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            contents: bytemuck::cast_slice(&vec![Visuals::of(0.0).to_raw(0.0); ncpus * DEPTH]),
+        });
+        Waterfall {
+            enabled: false,
+            ncpus,
+            instance_buffer,
+            value_buffer,
+        }
+    }
+
+    // How far back the rows reach, for fitting the floor underneath
+    pub fn extent(&self, spacing: f32) -> f32 {
+        if self.enabled {
+            (DEPTH as f32 + 1.0) * spacing / 2.0
+        } else {
+            0.0
+        }
+    }
+
+    // `scroll` is the fraction of the current sample period that has passed
+    pub fn update(
+        &self,
+        queue: &wgpu::Queue,
+        histories: &[&VecDeque<f32>],
+        instances: &[Instance],
+        highlights: &[f32],
+        spacing: f32,
+        scroll: f32,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let mut instance_data = Vec::with_capacity(self.ncpus * DEPTH);
+        let mut values = Vec::with_capacity(self.ncpus * DEPTH);
+        for (cpu, instance) in instances.iter().enumerate().take(self.ncpus) {
+            let history = histories[cpu];
+            for row in 0..DEPTH {
+                let offset =
+                    cgmath::Vector3::new(0.0, 0.0, -(row as f32 + 1.0 + scroll) * spacing / 2.0);
+                let model = cgmath::Matrix4::from_translation(instance.position() + offset)
+                    * cgmath::Matrix4::from_nonuniform_scale(0.8, 1.0, 0.4);
+                instance_data.push(InstanceRaw::from(model));
+                let value = history
+                    .len()
+                    .checked_sub(row + 1)
+                    .map_or(0.0, |i| history[i]);
+                // Only the first channel has a history
+                values.push(Visuals::of(value).to_raw(highlights[cpu] / 2.0));
+            }
+        }
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&instance_data),
+        );
+        queue.write_buffer(&self.value_buffer, 0, bytemuck::cast_slice(&values));
+    }
+
+    // Expects the pipeline, bind groups, mesh and index buffers to be set up by
+    // `SysMetrics::render`
+    pub fn render(&self, render_pass: &mut wgpu::RenderPass, num_indices: u32) {
+        if !self.enabled {
+            return;
+        }
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_vertex_buffer(2, self.value_buffer.slice(..));
+        render_pass.draw_indexed(0..num_indices, 0, 0..(self.ncpus * DEPTH) as u32);
+    }
+}
@@ -1,8 +1,7 @@
 use winit::{
     application::ApplicationHandler,
-    event::{ElementState, KeyEvent, WindowEvent},
+    event::WindowEvent,
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
-    keyboard::{Key, NamedKey},
     platform::wayland::WindowAttributesExtWayland,
     window::{Window, WindowId},
 };
@@ -12,29 +11,50 @@ use pollster::FutureExt;
 #[derive(Default)]
 struct App<'a> {
     state: Option<State<'a>>,
+    options: Options,
 }
 
+use crate::cli::Options;
 use crate::state::State;
 
-pub async fn run() {
+pub async fn run(options: Options) {
     env_logger::init();
     let event_loop = EventLoop::new().expect("Failed to create event loop");
 
     impl<'a> ApplicationHandler for App<'a> {
+        fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+            if let Some(state) = self.state.as_mut() {
+                state.save_views();
+            }
+        }
+
         fn resumed(&mut self, event_loop: &ActiveEventLoop) {
             let buttons = winit::window::WindowButtons::all();
 
-            let window_attributes = Window::default_attributes()
+            let mut window_attributes = Window::default_attributes()
                 .with_title("lolitop!")
-                .with_decorations(false)
+                .with_decorations(self.options.decorated)
                 .with_enabled_buttons(buttons)
                 .with_name("se.frikod.lolitop", "main")
                 .with_transparent(true)
                 .with_resizable(true);
+            if let Some((width, height)) = self.options.size {
+                window_attributes =
+                    window_attributes.with_inner_size(winit::dpi::PhysicalSize::new(width, height));
+            }
+            if let Some((x, y)) = self.options.position {
+                window_attributes =
+                    window_attributes.with_position(winit::dpi::PhysicalPosition::new(x, y));
+            }
 
             let window: Window = event_loop.create_window(window_attributes).unwrap();
-            let state = State::new(window).block_on();
-            self.state = Some(state);
+            match State::new(window, &self.options).block_on() {
+                Ok(state) => self.state = Some(state),
+                Err(e) => {
+                    eprintln!("lolitop: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
         }
 
         fn window_event(
@@ -43,23 +63,18 @@ pub async fn run() {
             _id: WindowId,
             event: WindowEvent,
         ) {
-            if !self.state.as_mut().unwrap().input(&event) {
+            let state = self.state.as_mut().unwrap();
+            if state.input(&event) {
+                if state.quit_requested() {
+                    event_loop.exit();
+                }
+            } else {
                 match event {
                     WindowEvent::CloseRequested => {
                         println!("The close button was pressed; stopping");
                         event_loop.exit();
                     }
 
-                    WindowEvent::KeyboardInput {
-                        event:
-                            KeyEvent {
-                                state: ElementState::Pressed,
-                                logical_key: Key::Named(NamedKey::Escape),
-                                ..
-                            },
-                        ..
-                    } => event_loop.exit(),
-
                     WindowEvent::RedrawRequested => {
                         let state = self.state.as_mut().unwrap();
                         state.update();
@@ -73,13 +88,6 @@ pub async fn run() {
                             Err(e) => eprintln!("{:?}", e),
                         }
                     }
-                    WindowEvent::MouseInput { button, .. }
-                        if button == winit::event::MouseButton::Left =>
-                    {
-                        let window = self.state.as_mut().unwrap().window();
-                        window.drag_window().unwrap();
-                    }
-
                     WindowEvent::Resized(physical_size) => {
                         let state = self.state.as_mut().unwrap();
                         state.resize(physical_size);
@@ -89,7 +97,10 @@ pub async fn run() {
             }
         }
     }
-    let mut app = App::default();
+    let mut app = App {
+        state: None,
+        options,
+    };
     event_loop.set_control_flow(ControlFlow::Poll);
     event_loop.run_app(&mut app).unwrap();
 }